@@ -1,4 +1,17 @@
 pub mod ad;
 pub mod identity_xlsx;
+pub mod identity_csv;
+pub mod accounts_csv;
+pub mod ldif;
+pub mod entra;
+pub mod scim;
+pub mod linux;
+pub mod aws_iam;
+pub mod postgres;
 pub mod dtos;
-pub mod csv;
\ No newline at end of file
+pub mod csv;
+pub mod traits;
+pub mod nesting;
+pub mod timestamps;
+pub mod diagnostics;
+pub mod json;
\ No newline at end of file
@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+use chrono::{DateTime, FixedOffset};
+use anyhow::{Result, anyhow};
+
+use super::csv::{self, CsvOptions};
+use super::dtos::{AccountDTO, AttributeValue, EntitlementDTO};
+use super::identity_csv::CsvColumnMeaning;
+use super::traits::TargetSystemConnector;
+use super::diagnostics::{LoadDiagnostic, ObjectDiagnostics};
+use super::nesting;
+use super::timestamps::parse_with_format;
+
+// Accounts and groups exported by an application as two CSV files
+#[derive(Debug, Clone)]
+pub struct CsvAccountConnector {
+    pub accounts_fp: String,
+    pub entitlements_fp: String,
+    pub options: CsvOptions,
+    // chrono format of the date columns (e.g. "%Y-%m-%d", "%d/%m/%Y %H:%M:%S %z"). Time and offset are optional
+    pub date_format: String,
+    // Separator of multi-valued cells (memberof, members...)
+    pub list_separator: char,
+
+    pub account_columns: Vec<CsvColumnMeaning>,
+    pub entitlement_columns: Vec<CsvColumnMeaning>,
+}
+impl CsvAccountConnector {
+    pub fn load_csv_accounts(&self) -> Result<Vec<AccountDTO>> {
+        self._load_csv_accounts(&mut Vec::new())
+    }
+    fn _load_csv_accounts(&self, diagnostics: &mut Vec<LoadDiagnostic>) -> Result<Vec<AccountDTO>> {
+        let rows: Vec<(usize, HashMap<String, String>)> = csv::read_numbered_with_options(&self.accounts_fp, &self.options)?;
+
+        rows.into_iter()
+            .map(|(line, row)| self._extract_account(row,
+                &mut ObjectDiagnostics { source: &self.accounts_fp, object_index: line, diagnostics }))
+            .collect()
+    }
+    fn _extract_account(&self, mut row: HashMap<String, String>, diag: &mut ObjectDiagnostics) -> Result<AccountDTO> {
+        let mut a = AccountDTO {
+            unique_id: "No ID".to_string(), alternate_ids: Vec::new(), sids: Vec::new(), display_name: None, description: None,
+            created: None, last_logon: None, password_last_set: None, expiration_date: None,
+            enabled: None, deleted: None, locked: None, flags: None,
+            memberof: None, ou: None, manager: None, other_attributes: HashMap::new(), position: Some(diag.position()) };
+
+        for column_meaning in &self.account_columns {
+            let value = self._take_column(&mut row, column_meaning, &self.accounts_fp)?;
+            match &column_meaning.attribute {
+                None => {a.other_attributes.insert(column_meaning.csv_column_name.clone(), value.map(AttributeValue::String));}
+                Some(field) => {
+                    match field.as_str() {
+                        "unique_id" => a.unique_id = CsvAccountConnector::_read_id(value, field, diag),
+                        "display_name" => a.display_name = value,
+                        "description" => a.description = value,
+                        "created" => a.created = self._read_date(value, field, diag),
+                        "last_logon" => a.last_logon = self._read_date(value, field, diag),
+                        "password_last_set" => a.password_last_set = self._read_date(value, field, diag),
+                        "expiration_date" => a.expiration_date = self._read_date(value, field, diag),
+                        "enabled" => a.enabled = CsvAccountConnector::_read_bool(value, field, diag),
+                        "deleted" => a.deleted = CsvAccountConnector::_read_bool(value, field, diag),
+                        "locked" => a.locked = CsvAccountConnector::_read_bool(value, field, diag),
+                        "memberof" => a.memberof = Some(self._read_list(value)),
+                        "ou" => a.ou = value,
+                        "manager" => a.manager = value,
+                        _ => {}
+                    }
+                }
+            }
+        }
+        Ok(a)
+    }
+
+    pub fn load_csv_groups(&self) -> Result<Vec<EntitlementDTO>> {
+        self._load_csv_groups(&mut Vec::new())
+    }
+    fn _load_csv_groups(&self, diagnostics: &mut Vec<LoadDiagnostic>) -> Result<Vec<EntitlementDTO>> {
+        let rows: Vec<(usize, HashMap<String, String>)> = csv::read_numbered_with_options(&self.entitlements_fp, &self.options)?;
+
+        let mut ents = rows.into_iter()
+            .map(|(line, row)| self._extract_entitlement(row,
+                &mut ObjectDiagnostics { source: &self.entitlements_fp, object_index: line, diagnostics }))
+            .collect::<Result<Vec<EntitlementDTO>>>()?;
+
+        // Post-processing (populate memberof)
+        if self.entitlement_columns.iter().any(|c| c.attribute.as_deref() == Some("member_groups")) {
+            nesting::calculate_memberof(&mut ents);
+        }
+
+        // Post-processing (nested groups crawling)
+        Ok(nesting::calculate_indirect_memberof(ents))
+    }
+    fn _extract_entitlement(&self, mut row: HashMap<String, String>, diag: &mut ObjectDiagnostics) -> Result<EntitlementDTO> {
+        let mut e = EntitlementDTO {
+            unique_id: "No ID".to_string(), alternate_ids: Vec::new(), sids: Vec::new(), display_name: None, description: None,
+            created: None, memberof: None, all_indirect_memberof: Vec::new(), members: None, member_groups: None, ou: None,
+            other_attributes: HashMap::new(), ts_owners: None, position: Some(diag.position()) };
+
+        for column_meaning in &self.entitlement_columns {
+            let value = self._take_column(&mut row, column_meaning, &self.entitlements_fp)?;
+            match &column_meaning.attribute {
+                None => {e.other_attributes.insert(column_meaning.csv_column_name.clone(), value.map(AttributeValue::String));}
+                Some(field) => {
+                    match field.as_str() {
+                        "unique_id" => e.unique_id = CsvAccountConnector::_read_id(value, field, diag),
+                        "display_name" => e.display_name = value,
+                        "description" => e.description = value,
+                        "created" => e.created = self._read_date(value, field, diag),
+                        "memberof" => e.memberof = Some(self._read_list(value)),
+                        "members" => e.members = Some(self._read_list(value)),
+                        "member_groups" => e.member_groups = Some(self._read_list(value)),
+                        "ou" => e.ou = value,
+                        "ts_owners" => e.ts_owners = Some(self._read_list(value)),
+                        _ => {}
+                    }
+                }
+            }
+        }
+        Ok(e)
+    }
+
+    // Removes the cell from the row. Empty cells are returned as None
+    fn _take_column(&self, row: &mut HashMap<String, String>, column_meaning: &CsvColumnMeaning, file_path: &str) -> Result<Option<String>> {
+        row.remove(&column_meaning.csv_column_name)
+            .map(|v| Some(v).filter(|v| !v.is_empty()))
+            .ok_or_else(|| anyhow!("Column name '{}' not found in the header of {}", column_meaning.csv_column_name, file_path))
+    }
+    fn _read_id(value: Option<String>, field: &str, diag: &mut ObjectDiagnostics) -> String {
+        // Preferable to continue with partial data
+        value.unwrap_or_else(|| {
+            diag.error(field, "Empty unique ID, loaded as \"No ID\"");
+            "No ID".to_string()
+        })
+    }
+    fn _read_date(&self, value: Option<String>, field: &str, diag: &mut ObjectDiagnostics) -> Option<DateTime<FixedOffset>> {
+        let v = value?;
+        let date = parse_with_format(&v, &self.date_format);
+        if date.is_none() {
+            diag.warning(field, &format!("Date '{}' doesn't match the format '{}', ignored", v, self.date_format));
+        }
+        date
+    }
+    fn _read_bool(value: Option<String>, field: &str, diag: &mut ObjectDiagnostics) -> Option<bool> {
+        let v = value?;
+        match v.trim().to_lowercase().as_str() {
+            "true" | "yes" | "y" | "1" => Some(true),
+            "false" | "no" | "n" | "0" => Some(false),
+            _ => {
+                diag.warning(field, &format!("'{}' is not a boolean, ignored", v));
+                None
+            }
+        }
+    }
+    fn _read_list(&self, value: Option<String>) -> Vec<String> {
+        value.map(|v| v.split(self.list_separator)
+                .map(|s| s.trim())
+                .filter(|s| !s.is_empty())
+                .map(|s| s.to_string())
+                .collect())
+            .unwrap_or_default()
+    }
+}
+impl TargetSystemConnector for CsvAccountConnector {
+    fn load_accounts(&self) -> Result<Vec<AccountDTO>> {
+        self.load_csv_accounts()
+    }
+    fn load_entitlements(&self) -> Result<Vec<EntitlementDTO>> {
+        self.load_csv_groups()
+    }
+    fn load_accounts_with_diagnostics(&self, diagnostics: &mut Vec<LoadDiagnostic>) -> Result<Vec<AccountDTO>> {
+        self._load_csv_accounts(diagnostics)
+    }
+    fn load_entitlements_with_diagnostics(&self, diagnostics: &mut Vec<LoadDiagnostic>) -> Result<Vec<EntitlementDTO>> {
+        self._load_csv_groups(diagnostics)
+    }
+}
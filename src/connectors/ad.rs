@@ -1,9 +1,14 @@
-use std::{fs, collections::{HashMap, HashSet}};
-use chrono::{NaiveDateTime, NaiveDate};
+use std::collections::HashMap;
+use chrono::{DateTime, FixedOffset};
 use serde_json::Value;
-use anyhow::{Result, Context};
+use anyhow::Result;
 
-use super::dtos::{AccountDTO, EntitlementDTO};
+use super::dtos::{AccountDTO, AccountFlags, AttributeValue, EntitlementDTO};
+use super::traits::TargetSystemConnector;
+use super::diagnostics::{LoadDiagnostic, ObjectDiagnostics};
+use super::nesting;
+use super::json::stream_json_objects;
+use super::timestamps::{parse_timestamp, from_filetime};
 
 #[derive(Debug, Clone)] 
 pub struct ADConnector {
@@ -15,167 +20,131 @@ pub struct ADConnector {
 }
 impl ADConnector {
     pub fn load_ad_users(&self) -> Result<Vec<AccountDTO>> { 
-        self._read_ad_users()
+        self._read_ad_users(&mut Vec::new())
     }
-    fn _read_ad_users(&self) -> Result<Vec<AccountDTO>> {
+    fn _read_ad_users(&self, diagnostics: &mut Vec<LoadDiagnostic>) -> Result<Vec<AccountDTO>> {
+        let mut index = 0;
+        let mapped = self.ad_user_attributes.mapped_attributes();
 
-        // Read the file content into a String
-        let file_content = fs::read_to_string(&self.ad_users_fp)
-            .with_context(|| format!("Failed to read file: {}", &self.ad_users_fp))?;
-
-        let all_objects: Vec<HashMap<String, Value>> = serde_json::from_str(&file_content)
-            .with_context(|| format!("Failed to deserialize json after opening file: {}", &self.ad_users_fp))?;
-       
+        // Objects are mapped while the file is read, one at a time
         // Unconventional use of serde to allow for configurable import formats
-        let accounts: Vec<AccountDTO> = all_objects
-            .into_iter()
-            .map(|user_values| {
-                self._parse_ad_user(&user_values)
-            })
-            .collect();
-        Ok(accounts)
-    }
-    fn _parse_ad_user(&self, user_data: &HashMap<String, Value>) -> AccountDTO {
-        let user_attribs = &self.ad_user_attributes;
-
-        let unique_id = user_data.get_string(&user_attribs.unique_id).unwrap_or("No ID".to_string()); // Preferable to continue with partial data
-        let description = user_data.get_string(&user_attribs.description);
-        let created = user_data.get_datetime(&user_attribs.created); 
-        let last_logon = user_data.get_datetime(&user_attribs.last_logon); 
-        let password_last_set = user_data.get_datetime(&user_attribs.password_last_set); 
-        let expiration_date = user_data.get_datetime(&user_attribs.expiration_date); 
-        let enabled = user_data.get_bool(&user_attribs.enabled);
-        let deleted = user_data.get_bool(&user_attribs.deleted);
-        let locked = user_data.get_bool(&user_attribs.locked);
-
-        let memberof = user_data.get_string_list(&user_attribs.memberof);
-        let ou = user_data.get_string(&user_attribs.ou);
-
-        let other_attributes: HashMap<String, Option<String>> = user_attribs.other_attributes
-                                            .iter()
-                                            .map(|k| (k.clone(), user_data.get_string(&Some(k.clone())) ))
-                                            .collect();
-        
-        let mut a = AccountDTO { unique_id, display_name: None, description, created, last_logon, password_last_set, expiration_date, enabled, deleted, locked, memberof, ou, other_attributes };
-        let set_display_name = user_attribs.display_name_fn;
-        set_display_name(&mut a);
-        a
+        stream_json_objects(&self.ad_users_fp, |user_values| {
+            let diag = &mut ObjectDiagnostics { source: &self.ad_users_fp, object_index: index, diagnostics };
+            self.ad_user_attributes.diagnose(&user_values, &mapped, diag);
+            index += 1;
+            AccountDTO { position: Some(diag.position()), ..self.ad_user_attributes.to_account_dto(&user_values) }
+        })
     }
-    
     pub fn load_ad_groups(&self) -> Result<Vec<EntitlementDTO>> { 
-        let mut ents = self._read_ad_groups()?;
-
-        // Post-processing (populate memberof)
-        if self.ad_group_attributes.member_groups.is_some() {
-            ADConnector::_calculate_memberof(&mut ents);
-        }
-        
-        // Post-processing (nested groups crawling)
-        let ents = ADConnector::_calculate_indirect_memberof(ents);
-        Ok(ents)
+        let ents = self._read_ad_groups(&mut Vec::new())?;
+        Ok(self.ad_group_attributes.post_process(ents))
     }
-    fn _read_ad_groups(&self) -> Result<Vec<EntitlementDTO>> {
+    fn _read_ad_groups(&self, diagnostics: &mut Vec<LoadDiagnostic>) -> Result<Vec<EntitlementDTO>> {
+        let mut index = 0;
+        let mapped = self.ad_group_attributes.mapped_attributes();
 
-        // Read the file content into a String
-        let file_content = fs::read_to_string(&self.ad_groups_fp)
-            .with_context(|| format!("Failed to read file: {}", &self.ad_groups_fp))?;
-    
-        let all_objects: Vec<HashMap<String, Value>> = serde_json::from_str(&file_content)
-            .with_context(|| format!("Failed to deserialize json after opening file: {}", &self.ad_groups_fp))?;
-        
+        // Objects are mapped while the file is read, one at a time
         // Unconventional use of serde to allow for configurable import formats
-        let ents: Vec<EntitlementDTO> = all_objects
-            .into_iter()
-            .map(|group_values| self._parse_ad_group(&group_values))
-            .collect();
-        Ok(ents)
+        stream_json_objects(&self.ad_groups_fp, |group_values| {
+            let diag = &mut ObjectDiagnostics { source: &self.ad_groups_fp, object_index: index, diagnostics };
+            self.ad_group_attributes.diagnose(&group_values, &mapped, diag);
+            index += 1;
+            EntitlementDTO { position: Some(diag.position()), ..self.ad_group_attributes.to_entitlement_dto(&group_values) }
+        })
     }
-    fn _parse_ad_group(&self, group_data: &HashMap<String, Value>) -> EntitlementDTO {
-        let group_attribs = &self.ad_group_attributes;
-
-        let unique_id = group_data.get_string(&group_attribs.unique_id).unwrap_or("No ID".to_string()); // Preferable to continue with partial data
-        let description = group_data.get_string(&group_attribs.description);
-        let created = group_data.get_datetime(&group_attribs.created); 
 
-        let memberof = group_data.get_string_list(&group_attribs.memberof);
-        let members = group_data.get_string_list(&group_attribs.members);
-        let member_groups = group_data.get_string_list(&group_attribs.member_groups);
-        let ou = group_data.get_string(&group_attribs.ou);
-        let ts_owners = group_data.get_string_list(&group_attribs.ts_owners);
-        
-        let other_attributes: HashMap<String, Option<String>> = group_attribs.other_attributes
-            .iter()
-            .map(|k| (k.clone(), group_data.get_string(&Some(k.clone())) ))
-            .collect();
-
-        let mut e = EntitlementDTO { unique_id, display_name: None, description, created, memberof, all_indirect_memberof: Vec::new(), members, member_groups, ou, other_attributes, ts_owners };
-        let set_display_name = group_attribs.display_name_fn;
-        set_display_name(&mut e);
-        e
+}
+impl TargetSystemConnector for ADConnector {
+    fn load_accounts(&self) -> Result<Vec<AccountDTO>> {
+        self.load_ad_users()
     }
-    fn _calculate_indirect_memberof(ents: Vec<EntitlementDTO>) -> Vec<EntitlementDTO> {
-        
-        // Consumes and transforms ents into a lookup structure
-        let mut ent_lookup = EntitlementLookup::from(ents);
-        
-        // HashMap of ent_uid and all indirect parents found
-        let mut indirect_parents_found: HashMap<String, Vec<String>> = HashMap::new(); 
-
-        // For each EntitlementDTO, it crawls for indirect memberships
-        for ent in ent_lookup.0.values() {
+    fn load_entitlements(&self) -> Result<Vec<EntitlementDTO>> {
+        self.load_ad_groups()
+    }
+    fn load_accounts_with_diagnostics(&self, diagnostics: &mut Vec<LoadDiagnostic>) -> Result<Vec<AccountDTO>> {
+        self._read_ad_users(diagnostics)
+    }
+    fn load_entitlements_with_diagnostics(&self, diagnostics: &mut Vec<LoadDiagnostic>) -> Result<Vec<EntitlementDTO>> {
+        let ents = self._read_ad_groups(diagnostics)?;
+        Ok(self.ad_group_attributes.post_process(ents))
+    }
+}
 
-            // Set structure to stop recurssion by not crawling on parents already found
-            let mut set: HashSet<&String> = HashSet::new();
-            if let Some(parents) = &ent.memberof {
-                let mut ps: Vec<&String> = parents.iter().collect(); // &Vec<String> to Vec<&String>
-                while ps.len() > 0 {
-                    let mut new_insertions = Vec::new();
-                    for p in ps {
-                        let p_ref = p;
-                        if set.insert(p){
-                            new_insertions.push(p_ref);
-                        }
-                    }
-                    ps = ent_lookup.get_parents_of_all_groups(new_insertions); 
-                }
-            } 
-            // Storing result of crawling for specific EntitlementDTO. Clones only necessary data to be stored
-            indirect_parents_found.insert(ent.unique_id.clone(), set.into_iter().map(|e| e.clone()).collect());
+// ConvertTo-Json replaces what's deeper than -Depth (2 by default) with the type name or the ToString() of the object:
+// "System.Object[]", "Microsoft.ActiveDirectory.Management.ADPropertyValueCollection", "@{Value=...}"
+fn is_truncated(value: &str) -> bool {
+    value == "System.Object[]"
+        || value.starts_with("Microsoft.ActiveDirectory.")
+        || value.starts_with("System.Collections.")
+        || (value.starts_with("@{") && value.ends_with('}'))
+}
+// The values of the mapped attributes that were lost in the export
+fn check_truncation(object: &HashMap<String, Value>, attributes: &[&str], diag: &mut ObjectDiagnostics) {
+    for attribute in attributes {
+        let truncated = match object.lookup_raw(attribute) {
+            Some(Value::String(s)) => is_truncated(s),
+            Some(Value::Array(values)) => values.iter().filter_map(Value::as_str).any(is_truncated),
+            _ => false,
+        };
+        if truncated {
+            diag.warning(attribute, "Value truncated by ConvertTo-Json, export again with a higher -Depth or select the property");
         }
+    }
+}
 
-        // Move found data to EntitlementsDTO
-        for ent in ent_lookup.0.values_mut() {
-            if let Some(all_indirect_parents) = indirect_parents_found.remove(&ent.unique_id){
-                ent.all_indirect_memberof = all_indirect_parents; 
-            }
+// What the mapping expects to find in an attribute
+#[derive(Clone, Copy)]
+enum Expected {
+    Id,
+    Text,
+    Date,
+    Bool,
+    // Boolean, or integer with the userAccountControl bits
+    BoolOrFlags,
+    Integer,
+    List,
+}
+// Values the mapping has to drop. Missing and null values aren't reported, only the unique ID is required
+fn check_attribute(object: &HashMap<String, Value>, key: &Option<String>, expected: Expected, diag: &mut ObjectDiagnostics) {
+    let Some(k) = key else { return };
+    let value = object.lookup(k);
+    if let Expected::Id = expected {
+        if value.and_then(Value::as_str).is_none_or(str::is_empty) {
+            diag.error(k, "Missing unique ID, loaded as \"No ID\"");
         }
-        let ret_val: Vec<EntitlementDTO> = ent_lookup.0.drain().map(|(_,v)| v).collect();
-        ret_val
+        return;
     }
-    fn _calculate_memberof(ents: &mut Vec<EntitlementDTO>) {
-        let mut group_memberofs = HashMap::new(); 
-            for e in ents.iter() {
-                if let Some(mem_groups) = &e.member_groups {
-                    for mem_group in mem_groups {
-                        // Creates HashMap of all memberof found so far. Clone is fine, this is new data being created
-                        let entry = group_memberofs.entry(mem_group.clone()).or_insert(HashSet::from([e.unique_id.clone()]));
-                        entry.insert(e.unique_id.clone());
-                    }
-                }
-            }
-            for e in ents {
-                let memberof_hm = group_memberofs.remove(&e.unique_id);
-                //Converts HashSet to Vec
-                e.memberof = memberof_hm.map(|hs| hs.into_iter().collect());
-            }
+    let Some(value) = value.filter(|v| !v.is_null() && v.as_str() != Some("")) else { return };
+    let problem = match expected {
+        Expected::Text if !value.is_string() => format!("{} is not a string, ignored", value),
+        Expected::Date if object.get_datetime(key).is_none() && !is_never(value) => format!("Unparseable date {}, ignored", value),
+        Expected::Bool if !value.is_boolean() => format!("{} is not a boolean, ignored", value),
+        Expected::BoolOrFlags if !value.is_boolean() && object.get_integer(key).is_none() => format!("{} is neither a boolean nor flags, ignored", value),
+        Expected::Integer if object.get_integer(key).is_none() => format!("{} is not an integer, ignored", value),
+        Expected::List if !value.is_array() && !value.is_string() => format!("{} is not a list of strings, ignored", value),
+        _ => return,
+    };
+    diag.warning(k, &problem);
+}
+// FILETIME values meaning "never" (lastLogon of accounts never used, accountExpires of accounts that don't expire)
+fn is_never(value: &Value) -> bool {
+    match value {
+        Value::Number(n) => n.as_i64().is_some_and(|n| n == 0 || n == i64::MAX),
+        Value::String(s) => s == "0" || s == "9223372036854775807",
+        _ => false,
     }
-
 }
 
+// Attributes are JSON keys, or paths to nested values ("Manager.DistinguishedName", "proxyAddresses[0]", "Enabled.Value")
 #[derive(Debug, Clone)]
 pub struct AdUserAttributes {
     pub description: Option<String>,
     pub unique_id: Option<String>,
+    // Attributes other objects may use to refer to the account (distinguishedName, sAMAccountName, objectGUID)
+    pub alternate_ids: Vec<String>,
+    // Needed to link the account to groups of other domains (foreign security principals)
+    pub object_sid: Option<String>,
+    pub sid_history: Option<String>,
 
     pub created: Option<String>,
     pub last_logon: Option<String>,
@@ -184,18 +153,98 @@ pub struct AdUserAttributes {
 
     pub enabled: Option<String>,
     pub deleted: Option<String>,
+    // Boolean, or msDS-User-Account-Control-Computed. AD doesn't keep the lockout bit of userAccountControl up to date
     pub locked: Option<String>,
+    // Raw userAccountControl integer. Gives enabled when it isn't mapped
+    pub user_account_control: Option<String>,
 
     pub memberof: Option<String>,
+    // RID of the primary group, which AD leaves out of memberOf. Resolved with the domain part of objectSid
+    pub primary_group_id: Option<String>,
     pub ou: Option<String>,
+    pub manager: Option<String>,
     pub other_attributes: Vec<String>, 
 
     pub display_name_fn: fn(&mut AccountDTO),
 }
+impl AdUserAttributes {
+    // Names of every attribute read from the exported objects
+    pub fn mapped_attributes(&self) -> Vec<&str> {
+        [&self.description, &self.unique_id, &self.object_sid, &self.sid_history, &self.created, &self.last_logon,
+            &self.password_last_set, &self.expiration_date, &self.enabled, &self.deleted, &self.locked,
+            &self.user_account_control, &self.memberof, &self.primary_group_id, &self.ou, &self.manager]
+            .into_iter()
+            .flatten()
+            .chain(&self.alternate_ids)
+            .chain(&self.other_attributes)
+            .map(|a| a.as_str())
+            .collect()
+    }
+    // Reports the values to_account_dto can't map. mapped is mapped_attributes(), built once per load
+    pub fn diagnose(&self, user_data: &HashMap<String, Value>, mapped: &[&str], diag: &mut ObjectDiagnostics) {
+        check_truncation(user_data, mapped, diag);
+        check_attribute(user_data, &self.unique_id, Expected::Id, diag);
+        for (key, expected) in [
+            (&self.description, Expected::Text), (&self.ou, Expected::Text), (&self.manager, Expected::Text),
+            (&self.created, Expected::Date), (&self.last_logon, Expected::Date),
+            (&self.password_last_set, Expected::Date), (&self.expiration_date, Expected::Date),
+            (&self.enabled, Expected::Bool), (&self.deleted, Expected::Bool), (&self.locked, Expected::BoolOrFlags),
+            (&self.user_account_control, Expected::Integer), (&self.primary_group_id, Expected::Integer),
+            (&self.memberof, Expected::List)] {
+            check_attribute(user_data, key, expected, diag);
+        }
+        if let (Some(key), Some(_)) = (&self.primary_group_id, user_data.get_integer(&self.primary_group_id)) {
+            if user_data.get_sids(&[&self.object_sid]).is_empty() {
+                diag.warning(key, "Primary group can't be resolved without objectSid, left out of memberof");
+            }
+        }
+    }
+    // Maps one exported object into an AccountDTO. Shared by every connector reading AD-like objects
+    pub fn to_account_dto(&self, user_data: &HashMap<String, Value>) -> AccountDTO {
+        let unique_id = user_data.get_string(&self.unique_id).unwrap_or("No ID".to_string()); // Preferable to continue with partial data
+        let description = user_data.get_string(&self.description);
+        let created = user_data.get_datetime(&self.created); 
+        let last_logon = user_data.get_datetime(&self.last_logon); 
+        let password_last_set = user_data.get_datetime(&self.password_last_set); 
+        let expiration_date = user_data.get_datetime(&self.expiration_date); 
+        let flags = user_data.get_integer(&self.user_account_control)
+            .map(|uac| AccountFlags::from_user_account_control(uac as u32));
+        let enabled = user_data.get_bool(&self.enabled).or(flags.as_ref().map(|f| !f.disabled));
+        let deleted = user_data.get_bool(&self.deleted);
+        // Unknown unless mapped, userAccountControl isn't reliable for lockouts
+        let locked = user_data.get_bool(&self.locked)
+            .or_else(|| user_data.get_integer(&self.locked).map(|computed| AccountFlags::from_user_account_control(computed as u32).locked));
+
+        let sids = user_data.get_sids(&[&self.object_sid, &self.sid_history]);
+        let object_sid = user_data.get_sids(&[&self.object_sid]).into_iter().next();
+        let mut memberof = user_data.get_string_list(&self.memberof);
+        if let Some(primary_group_sid) = primary_group_sid(object_sid.as_deref(), user_data.get_integer(&self.primary_group_id)) {
+            // The SID is resolved to the group when the target system is loaded
+            memberof.get_or_insert_with(Vec::new).push(primary_group_sid);
+        }
+        let ou = user_data.get_string(&self.ou);
+        let manager = user_data.get_string(&self.manager);
+        let alternate_ids = user_data.get_alternate_ids(&self.alternate_ids);
+
+        let other_attributes: HashMap<String, Option<AttributeValue>> = self.other_attributes
+                                            .iter()
+                                            .map(|k| (k.clone(), user_data.get_attribute_value(k) ))
+                                            .collect();
+        
+        let mut a = AccountDTO { unique_id, alternate_ids, sids, display_name: None, description, created, last_logon, password_last_set, expiration_date, enabled, deleted, locked, flags, memberof, ou, manager, other_attributes, position: None };
+        let set_display_name = self.display_name_fn;
+        set_display_name(&mut a);
+        a
+    }
+}
 
 #[derive(Debug, Clone)] 
 pub struct AdGroupAttributes{
     pub unique_id: Option<String>,
+    // Attributes other objects may use to refer to the group (distinguishedName, sAMAccountName, objectGUID)
+    pub alternate_ids: Vec<String>,
+    pub object_sid: Option<String>,
+    pub sid_history: Option<String>,
     pub description: Option<String>,
     
     pub created: Option<String>,
@@ -209,41 +258,138 @@ pub struct AdGroupAttributes{
     
     pub display_name_fn: fn(&mut EntitlementDTO),
 }
+impl AdGroupAttributes {
+    // Names of every attribute read from the exported objects
+    pub fn mapped_attributes(&self) -> Vec<&str> {
+        [&self.unique_id, &self.object_sid, &self.sid_history, &self.description, &self.created, &self.memberof,
+            &self.members, &self.member_groups, &self.ou, &self.ts_owners]
+            .into_iter()
+            .flatten()
+            .chain(&self.alternate_ids)
+            .chain(&self.other_attributes)
+            .map(|a| a.as_str())
+            .collect()
+    }
+    // Reports the values to_entitlement_dto can't map. mapped is mapped_attributes(), built once per load
+    pub fn diagnose(&self, group_data: &HashMap<String, Value>, mapped: &[&str], diag: &mut ObjectDiagnostics) {
+        check_truncation(group_data, mapped, diag);
+        check_attribute(group_data, &self.unique_id, Expected::Id, diag);
+        for (key, expected) in [
+            (&self.description, Expected::Text), (&self.ou, Expected::Text), (&self.created, Expected::Date),
+            (&self.memberof, Expected::List), (&self.members, Expected::List),
+            (&self.member_groups, Expected::List), (&self.ts_owners, Expected::List)] {
+            check_attribute(group_data, key, expected, diag);
+        }
+    }
+    // Maps one exported object into an EntitlementDTO. Shared by every connector reading AD-like objects
+    pub fn to_entitlement_dto(&self, group_data: &HashMap<String, Value>) -> EntitlementDTO {
+        let unique_id = group_data.get_string(&self.unique_id).unwrap_or("No ID".to_string()); // Preferable to continue with partial data
+        let description = group_data.get_string(&self.description);
+        let created = group_data.get_datetime(&self.created); 
 
-struct EntitlementLookup(HashMap<String, EntitlementDTO>);
-impl EntitlementLookup {
-    fn from(ents: Vec<EntitlementDTO>) -> Self {
-        let mut el = EntitlementLookup(HashMap::new());
-        for ent in ents {
-            el.0.insert(ent.unique_id.clone(), ent);
+        let memberof = group_data.get_string_list(&self.memberof);
+        let members = group_data.get_string_list(&self.members);
+        let member_groups = group_data.get_string_list(&self.member_groups);
+        let ou = group_data.get_string(&self.ou);
+        let ts_owners = group_data.get_string_list(&self.ts_owners);
+        let alternate_ids = group_data.get_alternate_ids(&self.alternate_ids);
+        let sids = group_data.get_sids(&[&self.object_sid, &self.sid_history]);
+        
+        let other_attributes: HashMap<String, Option<AttributeValue>> = self.other_attributes
+            .iter()
+            .map(|k| (k.clone(), group_data.get_attribute_value(k) ))
+            .collect();
+
+        let mut e = EntitlementDTO { unique_id, alternate_ids, sids, display_name: None, description, created, memberof, all_indirect_memberof: Vec::new(), members, member_groups, ou, other_attributes, ts_owners, position: None };
+        let set_display_name = self.display_name_fn;
+        set_display_name(&mut e);
+        e
+    }
+    pub fn post_process(&self, mut ents: Vec<EntitlementDTO>) -> Vec<EntitlementDTO> {
+
+        // Post-processing (populate memberof)
+        if self.member_groups.is_some() {
+            nesting::calculate_memberof(&mut ents);
         }
-        el
+        
+        // Post-processing (nested groups crawling)
+        nesting::calculate_indirect_memberof(ents)
     }
-    fn get_parents_of_all_groups(&self, ents_uids: Vec<&String>) -> Vec<&String> {
-        let mut accumulator = Vec::new();
-        for indirect_ents_uid in ents_uids {
-            if let Some(indirect_membership) = self.0.get(indirect_ents_uid) {
-                if let Some(parents) = &indirect_membership.memberof {
-                    accumulator.extend(parents.iter().collect::<Vec<&String>>()); 
-                    //To capture history. Instead of Vec<String>.. Vec<String(original),Vec<String(path)>
-                }
-            }
+}
+
+// Domain SID of the account (its objectSid without the last RID) followed by the RID of the primary group.
+// sIDHistory SIDs come from former domains, they can't be used
+fn primary_group_sid(object_sid: Option<&str>, primary_group_id: Option<i64>) -> Option<String> {
+    let (domain_sid, _) = object_sid?.rsplit_once('-')?;
+    Some(format!("{}-{}", domain_sid, primary_group_id?))
+}
+
+// Step of an attribute path: "Manager.DistinguishedName", "extensionAttributes.ext1", "proxyAddresses[0]"
+enum PathSegment<'a> {
+    Key(&'a str),
+    Index(usize),
+}
+fn parse_path(path: &str) -> Option<Vec<PathSegment<'_>>> {
+    let mut segments = Vec::new();
+    for part in path.split('.') {
+        let (key, mut indexes) = match part.find('[') {
+            Some(p) => (&part[..p], &part[p..]),
+            None => (part, ""),
+        };
+        if !key.is_empty() {
+            segments.push(PathSegment::Key(key));
+        }
+        while let Some(rest) = indexes.strip_prefix('[') {
+            let (index, after) = rest.split_once(']')?;
+            segments.push(PathSegment::Index(index.trim().parse().ok()?));
+            indexes = after;
+        }
+        if !indexes.is_empty() {
+            return None;
         }
-        accumulator
     }
+    Some(segments)
 }
-        
+
 trait ValueHashMap {
+    fn lookup_raw(&self, path: &str) -> Option<&Value>;
+    fn lookup(&self, path: &str) -> Option<&Value>;
     fn get_string(&self, key: &Option<String>) -> Option<String>;
-    fn get_datetime(&self, key: &Option<String>) -> Option<NaiveDate>;
+    fn get_datetime(&self, key: &Option<String>) -> Option<DateTime<FixedOffset>>;
     fn get_bool(&self, key: &Option<String>) -> Option<bool>;
+    fn get_integer(&self, key: &Option<String>) -> Option<i64>;
+    fn get_alternate_ids(&self, keys: &[String]) -> Vec<String>;
+    fn get_sids(&self, keys: &[&Option<String>]) -> Vec<String>;
+    fn get_attribute_value(&self, key: &str) -> Option<AttributeValue>;
     fn get_string_list(&self, key: &Option<String>) -> Option<Vec<String>>;
 }
 impl ValueHashMap for HashMap<String, Value> {
+    // Keys may contain dots, so an exact match wins over the path
+    fn lookup_raw(&self, path: &str) -> Option<&Value> {
+        if let Some(value) = self.get(path) {
+            return Some(value);
+        }
+        let segments = parse_path(path)?;
+        let mut value = match segments.first()? {
+            PathSegment::Key(key) => self.get(*key)?,
+            PathSegment::Index(_) => return None,
+        };
+        for segment in &segments[1..] {
+            value = match segment {
+                PathSegment::Key(key) => value.get(*key)?,
+                PathSegment::Index(i) => value.get(*i)?,
+            };
+        }
+        Some(value)
+    }
+    // Values truncated by ConvertTo-Json are missing values
+    fn lookup(&self, path: &str) -> Option<&Value> {
+        self.lookup_raw(path).filter(|v| !v.as_str().is_some_and(is_truncated))
+    }
     fn get_string(&self, key: &Option<String>) -> Option<String> {
         let value;
         if let Some(k) = key {
-            value = self.get(k)
+            value = self.lookup(k)
                 .and_then(Value::as_str) // Extracts &str if the Value is a string
                 .map(|s| s.to_string()); // Converts &str to String
 
@@ -252,46 +398,72 @@ impl ValueHashMap for HashMap<String, Value> {
         }
         value
     }
-    fn get_datetime(&self, key: &Option<String>) -> Option<NaiveDate> {
+    fn get_datetime(&self, key: &Option<String>) -> Option<DateTime<FixedOffset>> {
         // Return None if key is None
         let key = match key {
             Some(k) => k,
             None => return None,
         };
-        // Return None if no value associated with the key
-        let value_str = match self.get(key).and_then(Value::as_str) {
-            Some(value) => value,
-            None => return None,
-        };
-        // Attempt to extract and parse the timestamp
-        let timestamp = value_str.trim_start_matches("/Date(")
-                                  .trim_end_matches(")/")
-                                  .parse::<i64>();
         // Preferable to continue with missing data than stopping with error.
-        let timestamp = match timestamp {
-            Ok(t) => t,
-            Err(_) => return None,
-        };
-    
-        NaiveDateTime::from_timestamp_opt(timestamp / 1000, 0)
-            .and_then(|dt| Some(dt.date()))
+        match self.lookup(key)? {
+            Value::String(s) => parse_timestamp(s),
+            // FILETIME attributes are exported as numbers by Get-ADUser
+            Value::Number(n) => n.as_i64().and_then(from_filetime),
+            _ => None,
+        }
     }
     fn get_bool(&self, key: &Option<String>) -> Option<bool> {
         if let Some(k) = key {
-            self.get(k)
+            self.lookup(k)
                 .and_then(Value::as_bool) // Extracts bool if the Value is a bool
         } else {
             None
         }
     }
+    fn get_integer(&self, key: &Option<String>) -> Option<i64> {
+        // LDIF and CSV-like exports carry numbers as strings
+        match self.lookup(key.as_ref()?)? {
+            Value::Number(n) => n.as_i64(),
+            Value::String(s) => s.trim().parse().ok(),
+            _ => None,
+        }
+    }
+    fn get_alternate_ids(&self, keys: &[String]) -> Vec<String> {
+        keys.iter()
+            .filter_map(|k| self.get_string(&Some(k.clone())))
+            .collect()
+    }
+    fn get_sids(&self, keys: &[&Option<String>]) -> Vec<String> {
+        // Get-ADUser serializes SIDs as objects ({"BinaryLength": 28, "Value": "S-1-5-21-..."}), LDIF as strings
+        fn sid_values(value: &Value) -> Vec<String> {
+            match value {
+                Value::String(s) => vec![s.clone()],
+                Value::Object(o) => o.get("Value").and_then(Value::as_str).map(|s| vec![s.to_string()]).unwrap_or_default(),
+                Value::Array(values) => values.iter().flat_map(sid_values).collect(),
+                _ => Vec::new(),
+            }
+        }
+        keys.iter()
+            .filter_map(|k| self.lookup(k.as_ref()?))
+            .flat_map(sid_values)
+            .filter(|sid| sid.starts_with("S-"))
+            .collect()
+    }
+    fn get_attribute_value(&self, key: &str) -> Option<AttributeValue> {
+        self.lookup(key).and_then(AttributeValue::from_json)
+    }
     fn get_string_list(&self, key: &Option<String>) -> Option<Vec<String>> {
         let value: Vec<String>;
         if let Some(k) = key {
-            if let Some(Value::Array(groups)) = self.get(k) {
+            if let Some(Value::Array(groups)) = self.lookup(k) {
                 value = groups.iter()
                     .filter_map(|val| val.as_str())
+                    .filter(|s| !is_truncated(s))
                     .map(|s| s.to_string())
                     .collect();
+            } else if let Some(Value::String(single)) = self.lookup(k) {
+                // Exports omit the array when there is only one value
+                value = vec![single.clone()];
             } else {
                 // Preferable to continue with missing data
                 value = Vec::new()  
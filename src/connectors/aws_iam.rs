@@ -0,0 +1,434 @@
+use std::collections::HashMap;
+use chrono::{DateTime, FixedOffset};
+use serde_json::{Map, Value};
+use anyhow::Result;
+
+use super::dtos::{AccountDTO, AttributeValue, EntitlementDTO};
+use super::traits::TargetSystemConnector;
+use super::diagnostics::{LoadDiagnostic, ObjectDiagnostics, SourcePosition};
+use super::json::{read_json, get_string};
+use super::timestamps::parse_iso8601;
+use super::csv;
+use super::nesting;
+
+const ROOT_ACCOUNT: &str = "<root_account>";
+
+// One AWS account, from the output of `aws iam get-account-authorization-details`
+// and the IAM credential report (`aws iam get-credential-report`, decoded CSV).
+// Users (and optionally roles) are accounts, groups and policies are entitlements.
+// Everything is identified by its ARN; inline policies by the ARN of their owner followed by "#<policy name>".
+// Groups and roles are memberof their policies, so policies granted through a group are indirect access
+#[derive(Debug, Clone)]
+pub struct AwsIamConnector {
+    // One or more pages of the authorization details (a page, or an array of pages per file)
+    pub authorization_details_fps: Vec<String>,
+    pub credential_report_fp: Option<String>,
+    // Roles are not personal, but are reviewed with the users of the account
+    pub include_roles: bool,
+}
+impl AwsIamConnector {
+    pub fn load_iam_accounts(&self) -> Result<Vec<AccountDTO>> {
+        let details = AuthorizationDetails::read(&self.authorization_details_fps)?;
+        self._load_iam_accounts(&details, &mut Vec::new())
+    }
+    fn _load_iam_accounts(&self, details: &AuthorizationDetails, diagnostics: &mut Vec<LoadDiagnostic>) -> Result<Vec<AccountDTO>> {
+        let report = match &self.credential_report_fp {
+            Some(fp) => csv::read_numbered_with_options::<HashMap<String, String>, _>(fp, &csv::CsvOptions::default())?,
+            None => Vec::new(),
+        };
+        let report_by_user: HashMap<&str, &(usize, HashMap<String, String>)> = report.iter()
+            .filter_map(|r| r.1.get("user").map(|u| (u.as_str(), r)))
+            .collect();
+        let group_arns = details.group_arns();
+
+        details.check("UserDetailList", &details.users, &self.authorization_details_fps, diagnostics);
+        if self.include_roles {
+            details.check("RoleDetailList", &details.roles, &self.authorization_details_fps, diagnostics);
+        }
+        if let Some(fp) = &self.credential_report_fp {
+            let user_names: Vec<String> = details.users.iter().filter_map(|u| get_string(u, "UserName")).collect();
+            for (line, row) in &report {
+                check_report_row(row, &user_names, &mut ObjectDiagnostics { source: fp, object_index: *line, diagnostics });
+            }
+        }
+
+        let mut accounts = Vec::new();
+        if let (Some((line, root)), Some(fp)) = (report_by_user.get(ROOT_ACCOUNT), &self.credential_report_fp) {
+            accounts.push(AwsIamConnector::_root_account(root, SourcePosition::new(fp, *line)));
+        }
+        let users_source = list_source(&self.authorization_details_fps, "UserDetailList");
+        for (index, user) in details.users.iter().enumerate() {
+            let mut a = AwsIamConnector::_parse_principal(user, "User", &group_arns);
+            a.position = Some(SourcePosition::new(&users_source, index));
+            if let Some((_, row)) = get_string(user, "UserName").and_then(|name| report_by_user.get(name.as_str())) {
+                AwsIamConnector::_apply_credential_report(&mut a, row);
+            }
+            accounts.push(a);
+        }
+        if self.include_roles {
+            let roles_source = list_source(&self.authorization_details_fps, "RoleDetailList");
+            for (index, role) in details.roles.iter().enumerate() {
+                let mut a = AwsIamConnector::_parse_principal(role, "Role", &group_arns);
+                a.position = Some(SourcePosition::new(&roles_source, index));
+                a.last_logon = role.get("RoleLastUsed").and_then(Value::as_object)
+                    .and_then(|r| get_string(r, "LastUsedDate"))
+                    .and_then(|d| parse_iso8601(&d));
+                a.enabled = Some(true);
+                accounts.push(a);
+            }
+        }
+        Ok(accounts)
+    }
+    // Users and roles: "User" or "Role" is the prefix of their attribute names
+    fn _parse_principal(principal: &Map<String, Value>, kind: &str, group_arns: &HashMap<String, String>) -> AccountDTO {
+        let arn = get_string(principal, "Arn").unwrap_or("No ID".to_string()); // Preferable to continue with partial data
+
+        let mut memberof: Vec<String> = string_list(principal, "GroupList").iter()
+            .filter_map(|name| group_arns.get(name).cloned())
+            .collect();
+        memberof.extend(attached_policy_arns(principal));
+        memberof.extend(inline_policy_names(principal, &format!("{}PolicyList", kind)).iter().map(|p| inline_policy_id(&arn, p)));
+
+        let mut other_attributes = HashMap::new();
+        other_attributes.insert("type".to_string(), Some(kind.to_lowercase().into()));
+        other_attributes.insert("name".to_string(), get_string(principal, &format!("{}Name", kind)).map(AttributeValue::from));
+        other_attributes.insert("id".to_string(), get_string(principal, &format!("{}Id", kind)).map(AttributeValue::from));
+        other_attributes.insert("path".to_string(), get_string(principal, "Path").map(AttributeValue::from));
+        other_attributes.insert("permissions_boundary".to_string(), principal.get("PermissionsBoundary").and_then(Value::as_object)
+            .and_then(|b| get_string(b, "PermissionsBoundaryArn")).map(AttributeValue::from));
+        // Tags often carry the employee id or the owner, they're kept as "tag:<key>"
+        for tag in principal.get("Tags").and_then(Value::as_array).into_iter().flatten().filter_map(Value::as_object) {
+            if let Some(key) = get_string(tag, "Key") {
+                other_attributes.insert(format!("tag:{}", key), get_string(tag, "Value").map(AttributeValue::from));
+            }
+        }
+
+        AccountDTO {
+            unique_id: arn,
+            alternate_ids: Vec::new(),
+            sids: Vec::new(),
+            display_name: get_string(principal, &format!("{}Name", kind)),
+            description: get_string(principal, "Description"),
+            created: get_string(principal, "CreateDate").and_then(|d| parse_iso8601(&d)),
+            last_logon: None,
+            password_last_set: None,
+            expiration_date: None,
+            enabled: None,
+            deleted: None,
+            locked: None,
+            flags: None,
+            memberof: Some(memberof),
+            ou: None,
+            manager: None,
+            other_attributes,
+            position: None,
+        }
+    }
+    fn _root_account(row: &HashMap<String, String>, position: SourcePosition) -> AccountDTO {
+        let mut a = AccountDTO {
+            unique_id: row.get("arn").cloned().unwrap_or("No ID".to_string()), // Preferable to continue with partial data
+            alternate_ids: Vec::new(),
+            sids: Vec::new(),
+            display_name: Some(ROOT_ACCOUNT.to_string()),
+            description: None,
+            created: report_date(row, "user_creation_time"),
+            last_logon: None,
+            password_last_set: None,
+            expiration_date: None,
+            enabled: None,
+            deleted: None,
+            locked: None,
+            flags: None,
+            memberof: Some(Vec::new()),
+            ou: None,
+            manager: None,
+            other_attributes: HashMap::from([("type".to_string(), Some("root".into()))]),
+            position: Some(position),
+        };
+        AwsIamConnector::_apply_credential_report(&mut a, row);
+        // The root user can always sign in, password_enabled is "not_supported"
+        a.enabled = Some(true);
+        a
+    }
+    // Last use of the password or of any access key, and whether the user can authenticate at all
+    fn _apply_credential_report(a: &mut AccountDTO, row: &HashMap<String, String>) {
+        a.last_logon = ["password_last_used", "access_key_1_last_used_date", "access_key_2_last_used_date"].iter()
+            .filter_map(|k| report_date(row, k))
+            .max();
+        a.password_last_set = report_date(row, "password_last_changed");
+
+        let is_true = |k: &str| row.get(k).map(|v| v.eq_ignore_ascii_case("true"));
+        a.enabled = match (is_true("password_enabled"), is_true("access_key_1_active"), is_true("access_key_2_active")) {
+            (None, None, None) => None,
+            (p, k1, k2) => Some(p.unwrap_or(false) || k1.unwrap_or(false) || k2.unwrap_or(false)),
+        };
+        for k in ["password_enabled", "mfa_active", "access_key_1_active", "access_key_2_active"] {
+            // "true"/"false", or "N/A" and "not_supported" kept as text
+            let value = row.get(k).map(|v| match v.to_lowercase().as_str() {
+                "true" => AttributeValue::Bool(true),
+                "false" => AttributeValue::Bool(false),
+                _ => AttributeValue::String(v.clone()),
+            });
+            a.other_attributes.insert(k.to_string(), value);
+        }
+    }
+
+    pub fn load_iam_entitlements(&self) -> Result<Vec<EntitlementDTO>> {
+        let details = AuthorizationDetails::read(&self.authorization_details_fps)?;
+        self._load_iam_entitlements(&details, &mut Vec::new())
+    }
+    fn _load_iam_entitlements(&self, details: &AuthorizationDetails, diagnostics: &mut Vec<LoadDiagnostic>) -> Result<Vec<EntitlementDTO>> {
+        details.check("GroupDetailList", &details.groups, &self.authorization_details_fps, diagnostics);
+        details.check("Policies", &details.policies, &self.authorization_details_fps, diagnostics);
+
+        let mut ents: Vec<EntitlementDTO> = Vec::new();
+        // Policy ARN -> index in ents. Attached AWS managed policies may be missing from the Policies list
+        let mut policy_index: HashMap<String, usize> = HashMap::new();
+
+        let policies_source = list_source(&self.authorization_details_fps, "Policies");
+        for (index, policy) in details.policies.iter().enumerate() {
+            let mut e = AwsIamConnector::_parse_managed_policy(policy);
+            e.position = Some(SourcePosition::new(&policies_source, index));
+            policy_index.insert(e.unique_id.clone(), ents.len());
+            ents.push(e);
+        }
+
+        let mut principals: Vec<(&Map<String, Value>, &str)> = details.groups.iter().map(|g| (g, "Group")).collect();
+        principals.extend(details.users.iter().map(|u| (u, "User")));
+        if self.include_roles {
+            principals.extend(details.roles.iter().map(|r| (r, "Role")));
+        }
+
+        for (principal, kind) in principals {
+            let arn = get_string(principal, "Arn").unwrap_or("No ID".to_string()); // Preferable to continue with partial data
+
+            for policy_arn in attached_policy_arns(principal) {
+                let i = *policy_index.entry(policy_arn.clone()).or_insert_with(|| {
+                    ents.push(new_policy_entitlement(policy_arn.clone(), policy_arn.rsplit('/').next().map(|n| n.to_string()), "managed policy"));
+                    ents.len() - 1
+                });
+                add_member(&mut ents[i], &arn, kind == "Group");
+            }
+            for policy_name in inline_policy_names(principal, &format!("{}PolicyList", kind)) {
+                let mut e = new_policy_entitlement(inline_policy_id(&arn, &policy_name), Some(policy_name), "inline policy");
+                add_member(&mut e, &arn, kind == "Group");
+                ents.push(e);
+            }
+        }
+
+        let groups_source = list_source(&self.authorization_details_fps, "GroupDetailList");
+        for (index, group) in details.groups.iter().enumerate() {
+            let name = get_string(group, "GroupName");
+            let arn = get_string(group, "Arn").unwrap_or("No ID".to_string()); // Preferable to continue with partial data
+            let members: Vec<String> = details.users.iter()
+                .filter(|u| name.as_ref().is_some_and(|n| string_list(u, "GroupList").contains(n)))
+                .filter_map(|u| get_string(u, "Arn"))
+                .collect();
+            let mut memberof = attached_policy_arns(group);
+            memberof.extend(inline_policy_names(group, "GroupPolicyList").iter().map(|p| inline_policy_id(&arn, p)));
+
+            let mut other_attributes = HashMap::new();
+            other_attributes.insert("type".to_string(), Some("group".into()));
+            other_attributes.insert("id".to_string(), get_string(group, "GroupId").map(AttributeValue::from));
+            other_attributes.insert("path".to_string(), get_string(group, "Path").map(AttributeValue::from));
+
+            ents.push(EntitlementDTO {
+                unique_id: arn,
+                alternate_ids: Vec::new(),
+                sids: Vec::new(),
+                display_name: name,
+                description: None,
+                created: get_string(group, "CreateDate").and_then(|d| parse_iso8601(&d)),
+                memberof: Some(memberof),
+                all_indirect_memberof: Vec::new(),
+                members: Some(members),
+                member_groups: Some(Vec::new()),
+                ou: None,
+                other_attributes,
+                ts_owners: None,
+                position: Some(SourcePosition::new(&groups_source, index)),
+            });
+        }
+
+        // Post-processing (nested groups crawling: group -> policies)
+        Ok(nesting::calculate_indirect_memberof(ents))
+    }
+    fn _parse_managed_policy(policy: &Map<String, Value>) -> EntitlementDTO {
+        let arn = get_string(policy, "Arn").unwrap_or("No ID".to_string()); // Preferable to continue with partial data
+        let mut e = new_policy_entitlement(arn, get_string(policy, "PolicyName"), "managed policy");
+        e.description = get_string(policy, "Description");
+        e.created = get_string(policy, "CreateDate").and_then(|d| parse_iso8601(&d));
+        e.other_attributes.insert("id".to_string(), get_string(policy, "PolicyId").map(AttributeValue::from));
+        e.other_attributes.insert("path".to_string(), get_string(policy, "Path").map(AttributeValue::from));
+        e.other_attributes.insert("default_version".to_string(), get_string(policy, "DefaultVersionId").map(AttributeValue::from));
+        e
+    }
+}
+impl TargetSystemConnector for AwsIamConnector {
+    fn load_accounts(&self) -> Result<Vec<AccountDTO>> {
+        self.load_iam_accounts()
+    }
+    fn load_entitlements(&self) -> Result<Vec<EntitlementDTO>> {
+        self.load_iam_entitlements()
+    }
+    fn load_accounts_with_diagnostics(&self, diagnostics: &mut Vec<LoadDiagnostic>) -> Result<Vec<AccountDTO>> {
+        let details = AuthorizationDetails::read(&self.authorization_details_fps)?;
+        self._load_iam_accounts(&details, diagnostics)
+    }
+    fn load_entitlements_with_diagnostics(&self, diagnostics: &mut Vec<LoadDiagnostic>) -> Result<Vec<EntitlementDTO>> {
+        let details = AuthorizationDetails::read(&self.authorization_details_fps)?;
+        self._load_iam_entitlements(&details, diagnostics)
+    }
+    // The authorization details hold both, they are read once
+    fn load_all_with_diagnostics(&self, diagnostics: &mut Vec<LoadDiagnostic>) -> Result<(Vec<AccountDTO>, Vec<EntitlementDTO>)> {
+        let details = AuthorizationDetails::read(&self.authorization_details_fps)?;
+        Ok((self._load_iam_accounts(&details, diagnostics)?, self._load_iam_entitlements(&details, diagnostics)?))
+    }
+}
+
+// Lists of all pages of all files
+struct AuthorizationDetails {
+    users: Vec<Map<String, Value>>,
+    groups: Vec<Map<String, Value>>,
+    roles: Vec<Map<String, Value>>,
+    policies: Vec<Map<String, Value>>,
+}
+impl AuthorizationDetails {
+    fn read(fps: &[String]) -> Result<Self> {
+        let mut details = AuthorizationDetails { users: Vec::new(), groups: Vec::new(), roles: Vec::new(), policies: Vec::new() };
+        for fp in fps {
+            details.add_pages(read_json(fp)?);
+        }
+        Ok(details)
+    }
+    fn add_pages(&mut self, value: Value) {
+        match value {
+            Value::Array(pages) => {
+                for page in pages {
+                    self.add_pages(page);
+                }
+            }
+            Value::Object(mut page) => {
+                for (key, list) in [("UserDetailList", &mut self.users), ("GroupDetailList", &mut self.groups),
+                    ("RoleDetailList", &mut self.roles), ("Policies", &mut self.policies)] {
+                    if let Some(Value::Array(items)) = page.remove(key) {
+                        list.extend(items.into_iter().filter_map(|i| match i {
+                            Value::Object(o) => Some(o),
+                            _ => None,
+                        }));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    // Values of one of the lists the mapping drops
+    fn check(&self, list_name: &str, list: &[Map<String, Value>], fps: &[String], diagnostics: &mut Vec<LoadDiagnostic>) {
+        let source = list_source(fps, list_name);
+        let group_arns = self.group_arns();
+        for (index, object) in list.iter().enumerate() {
+            let diag = &mut ObjectDiagnostics { source: &source, object_index: index, diagnostics };
+            if get_string(object, "Arn").is_none() {
+                diag.error("Arn", "Missing ARN, loaded as \"No ID\"");
+            }
+            if let Some(created) = get_string(object, "CreateDate").filter(|d| parse_iso8601(d).is_none()) {
+                diag.warning("CreateDate", &format!("Unparseable date '{}', ignored", created));
+            }
+            for group in string_list(object, "GroupList").iter().filter(|g| !group_arns.contains_key(*g)) {
+                diag.warning("GroupList", &format!("Group '{}' not found in GroupDetailList, membership ignored", group));
+            }
+        }
+    }
+    // Users refer to their groups by name
+    fn group_arns(&self) -> HashMap<String, String> {
+        self.groups.iter()
+            .filter_map(|g| Some((get_string(g, "GroupName")?, get_string(g, "Arn")?)))
+            .collect()
+    }
+}
+
+// Pages are concatenated, the object index of a user, group, role or policy is its position in the list
+fn list_source(fps: &[String], list_name: &str) -> String {
+    format!("{} ({})", fps.join(", "), list_name)
+}
+fn new_policy_entitlement(unique_id: String, display_name: Option<String>, policy_type: &str) -> EntitlementDTO {
+    let mut other_attributes = HashMap::new();
+    other_attributes.insert("type".to_string(), Some(policy_type.into()));
+    other_attributes.insert("aws_managed".to_string(), Some(unique_id.starts_with("arn:aws:iam::aws:policy/").into()));
+    EntitlementDTO {
+        unique_id,
+        alternate_ids: Vec::new(),
+        sids: Vec::new(),
+        display_name,
+        description: None,
+        created: None,
+        memberof: Some(Vec::new()),
+        all_indirect_memberof: Vec::new(),
+        members: Some(Vec::new()),
+        member_groups: Some(Vec::new()),
+        ou: None,
+        other_attributes,
+        ts_owners: None,
+        position: None,
+    }
+}
+fn add_member(e: &mut EntitlementDTO, member: &str, is_group: bool) {
+    if let Some(members) = &mut e.members {
+        members.push(member.to_string());
+    }
+    if is_group {
+        if let Some(member_groups) = &mut e.member_groups {
+            member_groups.push(member.to_string());
+        }
+    }
+}
+
+fn inline_policy_id(owner_arn: &str, policy_name: &str) -> String {
+    format!("{}#{}", owner_arn, policy_name)
+}
+fn attached_policy_arns(principal: &Map<String, Value>) -> Vec<String> {
+    principal.get("AttachedManagedPolicies")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_object)
+        .filter_map(|p| get_string(p, "PolicyArn"))
+        .collect()
+}
+fn inline_policy_names(principal: &Map<String, Value>, list_name: &str) -> Vec<String> {
+    principal.get(list_name)
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_object)
+        .filter_map(|p| get_string(p, "PolicyName"))
+        .collect()
+}
+fn string_list(object: &Map<String, Value>, key: &str) -> Vec<String> {
+    object.get(key)
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .map(|s| s.to_string())
+        .collect()
+}
+// Report rows of users missing from the authorization details, and dates that aren't placeholders nor ISO 8601
+fn check_report_row(row: &HashMap<String, String>, user_names: &[String], diag: &mut ObjectDiagnostics) {
+    let user = row.get("user").map(|u| u.as_str()).unwrap_or_default();
+    if user != ROOT_ACCOUNT && !user_names.iter().any(|n| n == user) {
+        diag.warning("user", &format!("User '{}' not found in the authorization details, row ignored", user));
+    }
+    for k in ["user_creation_time", "password_last_used", "password_last_changed", "access_key_1_last_used_date", "access_key_2_last_used_date"] {
+        if let Some(value) = row.get(k) {
+            let placeholder = ["N/A", "no_information", "not_supported", ""].contains(&value.as_str());
+            if !placeholder && parse_iso8601(value).is_none() {
+                diag.warning(k, &format!("Unparseable date '{}', ignored", value));
+            }
+        }
+    }
+}
+// Credential report dates are ISO 8601, or "N/A" / "no_information" / "not_supported"
+fn report_date(row: &HashMap<String, String>, key: &str) -> Option<DateTime<FixedOffset>> {
+    row.get(key).and_then(|d| parse_iso8601(d))
+}
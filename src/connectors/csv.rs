@@ -1,27 +1,86 @@
-use csv::Reader;
+use csv::ReaderBuilder;
+use encoding_rs::Encoding;
+use serde::Serialize;
 use serde::de::DeserializeOwned;
 use std::error::Error;
-use std::fs::File;
+use std::fs;
 use std::path::Path;
+use anyhow::{Result, Context, anyhow};
 
 
+// Format of the CSV files as exported by the source system
+#[derive(Debug, Clone, Serialize)]
+pub struct CsvOptions {
+    pub delimiter: u8,
+    pub quote: u8,
+    pub quoting: bool,
+    pub double_quote: bool,
+    pub escape: Option<u8>,
+    pub trim: bool,
+    // Any label known to the WHATWG encoding standard ("utf-8", "windows-1252", "iso-8859-1", "utf-16le"...).
+    // A byte order mark in the file takes precedence over this setting
+    pub encoding: String,
+}
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            quote: b'"',
+            quoting: true,
+            double_quote: true,
+            escape: None,
+            trim: false,
+            encoding: "utf-8".to_string(),
+        }
+    }
+}
 
 // Generic function to read a CSV file into a Vec of any type that implements DeserializeOwned
 pub fn read<T: DeserializeOwned, P: AsRef<Path>>(file_path: P) -> Result<Vec<T>, Box<dyn Error>> {
+    Ok(read_with_options(file_path, &CsvOptions::default())?)
+}
+
+// Same as read, with configurable format and encoding
+pub fn read_with_options<T: DeserializeOwned, P: AsRef<Path>>(file_path: P, options: &CsvOptions) -> Result<Vec<T>> {
+    Ok(read_numbered_with_options(file_path, options)?.into_iter().map(|(_, record)| record).collect())
+}
+
+// Records along with the line of the file where each one starts (1 based, the header is line 1)
+pub fn read_numbered_with_options<T: DeserializeOwned, P: AsRef<Path>>(file_path: P, options: &CsvOptions) -> Result<Vec<(usize, T)>> {
+    let file_path = file_path.as_ref();
 
-    let file = File::open(file_path)?;
+    let bytes = fs::read(file_path)
+        .with_context(|| format!("Failed to read file: {}", file_path.display()))?;
 
-    let mut rdr = Reader::from_reader(file);
-    
-    // Collect deserialized records into a Vec<T>
+    // Decodes the whole file to UTF-8. The BOM (if any) is removed and determines the encoding
+    let encoding = Encoding::for_label(options.encoding.as_bytes())
+        .ok_or_else(|| anyhow!("Unknown encoding '{}' configured for file: {}", options.encoding, file_path.display()))?;
+    let (content, _, _) = encoding.decode(&bytes);
+
+    let mut rdr = ReaderBuilder::new()
+        .delimiter(options.delimiter)
+        .quote(options.quote)
+        .quoting(options.quoting)
+        .double_quote(options.double_quote)
+        .escape(options.escape)
+        .trim(if options.trim { csv::Trim::All } else { csv::Trim::None })
+        .from_reader(content.as_bytes());
+
+    let headers = rdr.headers()
+        .with_context(|| format!("Failed to read the header of file: {}", file_path.display()))?
+        .clone();
+
+    // Collect deserialized records into a Vec<(line, T)>
     let mut records = Vec::new();
-    for result in rdr.deserialize() {
-        let record: T = result?;
-        records.push(record);
+    for (i, result) in rdr.records().enumerate() {
+        let raw = result
+            .with_context(|| format!("Failed to read record {} of file: {}", i + 1, file_path.display()))?;
+        // Quoted values can span several lines, the position is the only reliable line number
+        let line = raw.position().map_or(i + 2, |p| p.line() as usize);
+        let record: T = raw.deserialize(Some(&headers))
+            .with_context(|| format!("Failed to deserialize line {} of file: {}", line, file_path.display()))?;
+        records.push((line, record));
     }
 
     Ok(records)
 }
-
-
-
@@ -0,0 +1,83 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    // Data was loaded, possibly incomplete
+    Warning,
+    // Data was dropped or replaced by a placeholder
+    Error,
+}
+
+// Problem found in the source data while loading it. Connectors continue with partial data,
+// diagnostics keep track of what was lost
+#[derive(Debug, Clone)]
+pub struct LoadDiagnostic {
+    pub severity: Severity,
+    // File the object was read from
+    pub source: String,
+    // Position of the object in the file (0 based, line or row number for CSV files and spreadsheets), when the problem concerns one object
+    pub object_index: Option<usize>,
+    pub attribute: Option<String>,
+    pub problem: String,
+}
+impl LoadDiagnostic {
+    pub fn warning(source: &str, object_index: Option<usize>, attribute: Option<&str>, problem: &str) -> Self {
+        Self {
+            severity: Severity::Warning,
+            source: source.to_string(),
+            object_index,
+            attribute: attribute.map(|a| a.to_string()),
+            problem: problem.to_string(),
+        }
+    }
+    pub fn error(source: &str, object_index: Option<usize>, attribute: Option<&str>, problem: &str) -> Self {
+        Self { severity: Severity::Error, ..Self::warning(source, object_index, attribute, problem) }
+    }
+}
+impl fmt::Display for LoadDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} in {}", self.severity, self.source)?;
+        if let Some(i) = self.object_index {
+            write!(f, ", object {}", i)?;
+        }
+        if let Some(a) = &self.attribute {
+            write!(f, ", attribute {}", a)?;
+        }
+        write!(f, ": {}", self.problem)
+    }
+}
+
+// File and position an object was read from, as given in its diagnostics
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourcePosition {
+    pub source: String,
+    pub object_index: usize,
+}
+impl SourcePosition {
+    pub fn new(source: &str, object_index: usize) -> Self {
+        Self { source: source.to_string(), object_index }
+    }
+}
+impl fmt::Display for SourcePosition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}, object {}", self.source, self.object_index)
+    }
+}
+
+// Diagnostics of the object being mapped, so mappers don't have to carry the file and position around
+pub struct ObjectDiagnostics<'a> {
+    pub source: &'a str,
+    pub object_index: usize,
+    pub diagnostics: &'a mut Vec<LoadDiagnostic>,
+}
+impl ObjectDiagnostics<'_> {
+    pub fn position(&self) -> SourcePosition {
+        SourcePosition::new(self.source, self.object_index)
+    }
+    pub fn warning(&mut self, attribute: &str, problem: &str) {
+        self.diagnostics.push(LoadDiagnostic::warning(self.source, Some(self.object_index), Some(attribute), problem));
+    }
+    pub fn error(&mut self, attribute: &str, problem: &str) {
+        self.diagnostics.push(LoadDiagnostic::error(self.source, Some(self.object_index), Some(attribute), problem));
+    }
+}
@@ -1,7 +1,11 @@
 use std::collections::HashMap;
-use chrono::NaiveDate;
+use chrono::{DateTime, FixedOffset, NaiveDate};
+use serde::Serialize;
+use serde_json::Value;
 
-#[derive(Debug)]
+use super::diagnostics::SourcePosition;
+
+#[derive(Debug, Default)]
 pub struct IdentityDTO {
     pub unique_id: String,
     pub first_name: String,
@@ -9,69 +13,277 @@ pub struct IdentityDTO {
     pub email: String,
     pub employee_no: String,
     pub employee_type: String,
-    pub enabled: Option<bool>,
+    pub status: Option<IdentityStatus>,
     pub manager_key: String,
     pub hire_date: Option<NaiveDate>,
     pub termination_date: Option<NaiveDate>,
 
     pub attributes: HashMap<String, String>, 
 
+    // Where the record was read, when the source has positions (reported with duplicates)
+    pub position: Option<SourcePosition>,
 }
-impl Default for IdentityDTO {
-    fn default () -> Self {
-        Self {
-            unique_id: String::new(),
-            first_name: String::new(),
-            last_name: String::new(),
-            email: String::new(),
-            employee_no: String::new(),
-            employee_type: String::new(),
-            enabled: None,
-            manager_key: String::new(),
-            hire_date: None,
-            termination_date: None,
-
-            attributes: HashMap::new(),
+
+// Lifecycle status of an identity, whatever the encoding of the HR system
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum IdentityStatus {
+    Active,
+    LeaveOfAbsence,
+    Suspended,
+    Terminated,
+    // Hired, not started yet
+    PreHire,
+}
+
+// Raw status values of an identity source (codes or labels) and the status they mean.
+// Values are compared trimmed and case-insensitive, numbers without decimals ("3", 3 and 3.0 are the same)
+#[derive(Debug, Clone, Serialize)]
+pub struct StatusMapping {
+    pub values: HashMap<String, IdentityStatus>,
+}
+impl StatusMapping {
+    pub fn new() -> Self {
+        Self { values: HashMap::new() }
+    }
+    pub fn add(mut self, raw_value: &str, status: IdentityStatus) -> Self {
+        self.values.insert(StatusMapping::_normalize(raw_value), status);
+        self
+    }
+    pub fn get(&self, raw_value: &str) -> Option<IdentityStatus> {
+        self.values.get(&StatusMapping::_normalize(raw_value)).copied()
+    }
+    fn _normalize(raw_value: &str) -> String {
+        let value = raw_value.trim();
+        match value.parse::<f64>() {
+            Ok(n) if n.fract() == 0.0 => (n as i64).to_string(),
+            _ => value.to_lowercase(),
         }
     }
 }
+// Encoding of the original HR extract: 0 is disabled, 3 is enabled. Disabled doesn't tell a termination apart,
+// these identities are leavers once past their termination date
+impl Default for StatusMapping {
+    fn default() -> Self {
+        StatusMapping::new()
+            .add("0", IdentityStatus::Suspended)
+            .add("3", IdentityStatus::Active)
+    }
+}
 
 #[derive(Debug)]
 pub struct AccountDTO {
     pub unique_id: String,
+    // Other identifiers the account is referred to by (DN, sAMAccountName, objectGUID...)
+    pub alternate_ids: Vec<String>,
+    // objectSid followed by the sIDHistory values
+    pub sids: Vec<String>,
     pub display_name: Option<String>,
     pub description: Option<String>,
     
-    pub created: Option<NaiveDate>,
-    pub last_logon: Option<NaiveDate>,
-    pub password_last_set: Option<NaiveDate>,
-    pub expiration_date: Option<NaiveDate>,
+    pub created: Option<DateTime<FixedOffset>>,
+    pub last_logon: Option<DateTime<FixedOffset>>,
+    pub password_last_set: Option<DateTime<FixedOffset>>,
+    pub expiration_date: Option<DateTime<FixedOffset>>,
 
     pub enabled: Option<bool>,
     pub deleted: Option<bool>,
     pub locked: Option<bool>,
+    pub flags: Option<AccountFlags>,
 
     pub memberof: Option<Vec<String>>,
     pub ou: Option<String>,
-    pub other_attributes: HashMap<String, Option<String>>, 
+    pub manager: Option<String>,
+    pub other_attributes: HashMap<String, Option<AttributeValue>>, 
+
+    // Where the account was read, when the source has positions (reported with duplicates)
+    pub position: Option<SourcePosition>,
+}
+
+// Bits of AD userAccountControl (and msDS-User-Account-Control-Computed)
+#[derive(Debug, Clone, Default)]
+pub struct AccountFlags {
+    pub script: bool,
+    pub disabled: bool,
+    pub home_dir_required: bool,
+    pub locked: bool,
+    pub password_not_required: bool,
+    pub password_cant_change: bool,
+    pub encrypted_text_password_allowed: bool,
+    pub temp_duplicate_account: bool,
+    pub normal_account: bool,
+    pub interdomain_trust_account: bool,
+    pub workstation_trust_account: bool,
+    pub server_trust_account: bool,
+    pub password_never_expires: bool,
+    pub mns_logon_account: bool,
+    pub smartcard_required: bool,
+    pub trusted_for_delegation: bool,
+    pub not_delegated: bool,
+    pub use_des_key_only: bool,
+    pub dont_require_preauth: bool,
+    pub password_expired: bool,
+    pub trusted_to_auth_for_delegation: bool,
+    pub partial_secrets_account: bool,
+}
+impl AccountFlags {
+    pub fn from_user_account_control(uac: u32) -> Self {
+        let bit = |mask: u32| uac & mask != 0;
+        Self {
+            script: bit(0x0001),
+            disabled: bit(0x0002),
+            home_dir_required: bit(0x0008),
+            locked: bit(0x0010),
+            password_not_required: bit(0x0020),
+            password_cant_change: bit(0x0040),
+            encrypted_text_password_allowed: bit(0x0080),
+            temp_duplicate_account: bit(0x0100),
+            normal_account: bit(0x0200),
+            interdomain_trust_account: bit(0x0800),
+            workstation_trust_account: bit(0x1000),
+            server_trust_account: bit(0x2000),
+            password_never_expires: bit(0x10000),
+            mns_logon_account: bit(0x20000),
+            smartcard_required: bit(0x40000),
+            trusted_for_delegation: bit(0x80000),
+            not_delegated: bit(0x100000),
+            use_des_key_only: bit(0x200000),
+            dont_require_preauth: bit(0x400000),
+            password_expired: bit(0x800000),
+            trusted_to_auth_for_delegation: bit(0x1000000),
+            partial_secrets_account: bit(0x4000000),
+        }
+    }
+    // Flags set, as shown in the reports. "Normal account" is left out, nearly every account has it.
+    // The lockout bit is left out too: AD doesn't keep it up to date in userAccountControl, see AccountDTO.locked
+    pub fn names(&self) -> Vec<&'static str> {
+        [
+            (self.script, "Logon script"),
+            (self.disabled, "Disabled"),
+            (self.home_dir_required, "Home directory required"),
+            (self.password_not_required, "Password not required"),
+            (self.password_cant_change, "Password can't change"),
+            (self.encrypted_text_password_allowed, "Reversible password encryption"),
+            (self.temp_duplicate_account, "Temporary duplicate account"),
+            (self.interdomain_trust_account, "Interdomain trust account"),
+            (self.workstation_trust_account, "Workstation trust account"),
+            (self.server_trust_account, "Server trust account"),
+            (self.password_never_expires, "Password never expires"),
+            (self.mns_logon_account, "MNS logon account"),
+            (self.smartcard_required, "Smartcard required"),
+            (self.trusted_for_delegation, "Trusted for delegation"),
+            (self.not_delegated, "Not delegated"),
+            (self.use_des_key_only, "DES keys only"),
+            (self.dont_require_preauth, "Kerberos preauth not required"),
+            (self.password_expired, "Password expired"),
+            (self.trusted_to_auth_for_delegation, "Trusted to authenticate for delegation"),
+            (self.partial_secrets_account, "Read-only DC"),
+        ]
+        .into_iter()
+        .filter(|(set, _)| *set)
+        .map(|(_, name)| name)
+        .collect()
+    }
+}
 
+// Value of an attribute that has no field of its own, keeping the type and every value found in the source
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttributeValue {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Bool(bool),
+    // Multi-valued attributes (proxyAddresses, servicePrincipalName...)
+    List(Vec<AttributeValue>),
+}
+impl AttributeValue {
+    // Null is no value. Objects are kept as their JSON text
+    pub fn from_json(value: &Value) -> Option<Self> {
+        match value {
+            Value::Null => None,
+            Value::String(s) => Some(Self::String(s.clone())),
+            Value::Bool(b) => Some(Self::Bool(*b)),
+            Value::Number(n) => n.as_i64().map(Self::Integer).or(n.as_f64().map(Self::Float)),
+            Value::Array(values) => Some(Self::List(values.iter().filter_map(Self::from_json).collect())),
+            Value::Object(_) => Some(Self::String(value.to_string())),
+        }
+    }
+    // Single values are a list of one
+    pub fn values(&self) -> Vec<&AttributeValue> {
+        match self {
+            Self::List(values) => values.iter().collect(),
+            single => vec![single],
+        }
+    }
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(s) => Some(s),
+            _ => None,
+        }
+    }
+    // True when any of the values is the given text (case insensitive)
+    pub fn contains(&self, text: &str) -> bool {
+        self.values().iter().any(|v| v.to_string().eq_ignore_ascii_case(text))
+    }
+}
+impl std::fmt::Display for AttributeValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::String(s) => write!(f, "{}", s),
+            Self::Integer(n) => write!(f, "{}", n),
+            Self::Float(n) => write!(f, "{}", n),
+            Self::Bool(b) => write!(f, "{}", b),
+            Self::List(values) => write!(f, "{}", values.iter().map(|v| v.to_string()).collect::<Vec<String>>().join("; ")),
+        }
+    }
+}
+impl From<String> for AttributeValue {
+    fn from(s: String) -> Self {
+        Self::String(s)
+    }
+}
+impl From<&str> for AttributeValue {
+    fn from(s: &str) -> Self {
+        Self::String(s.to_string())
+    }
+}
+impl From<bool> for AttributeValue {
+    fn from(b: bool) -> Self {
+        Self::Bool(b)
+    }
+}
+impl From<i64> for AttributeValue {
+    fn from(n: i64) -> Self {
+        Self::Integer(n)
+    }
+}
+impl From<Vec<String>> for AttributeValue {
+    fn from(values: Vec<String>) -> Self {
+        Self::List(values.into_iter().map(Self::String).collect())
+    }
 }
 
 #[derive(Debug)] 
 pub struct EntitlementDTO {
     pub unique_id: String,
+    // Other identifiers the entitlement is referred to by (DN, sAMAccountName, objectGUID...)
+    pub alternate_ids: Vec<String>,
+    // objectSid followed by the sIDHistory values
+    pub sids: Vec<String>,
     pub display_name: Option<String>,
     pub description: Option<String>,
     
-    pub created: Option<NaiveDate>,
+    pub created: Option<DateTime<FixedOffset>>,
     pub memberof: Option<Vec<String>>,
     pub all_indirect_memberof: Vec<String>,
     pub members: Option<Vec<String>>,
     pub member_groups: Option<Vec<String>>,
     pub ou: Option<String>,
 
-    pub other_attributes: HashMap<String, Option<String>>, 
+    pub other_attributes: HashMap<String, Option<AttributeValue>>, 
     pub ts_owners: Option<Vec<String>>,
+
+    // Where the entitlement was read, when the source has positions (reported with duplicates)
+    pub position: Option<SourcePosition>,
 }
 
 
@@ -0,0 +1,346 @@
+use std::collections::{HashMap, HashSet};
+use serde_json::{Map, Value};
+use anyhow::{Result, anyhow};
+
+use super::dtos::{AccountDTO, AttributeValue, EntitlementDTO};
+use super::traits::TargetSystemConnector;
+use super::diagnostics::{LoadDiagnostic, ObjectDiagnostics, SourcePosition};
+use super::json::{self, Paging, PagedFile, read_json, flatten_pages, get_date, get_string};
+use super::timestamps::parse_iso8601;
+use super::nesting;
+
+// Offline Microsoft Graph JSON dumps of an Entra ID (Azure AD) tenant.
+// Every file can hold one Graph page ({"@odata.context": .., "value": [..], "@odata.nextLink": ..}),
+// an array of pages, or a plain array of objects. All pages of all files are concatenated.
+// Objects are identified by their Graph id, on-premises identifiers are kept as other attributes
+// so the tenant can be paired with the on-premises domain (ts_sync)
+#[derive(Debug, Clone)]
+pub struct EntraConnector {
+    // /users (with signInActivity if available)
+    pub users_fps: Vec<String>,
+    // /groups, optionally with $expand=members,owners
+    pub groups_fps: Vec<String>,
+    // Members of each group as {"<group id>": <page(s) of /groups/{id}/members>}
+    pub group_members_fps: Vec<String>,
+    // /roleManagement/directory/roleDefinitions
+    pub role_definitions_fps: Vec<String>,
+    // /roleManagement/directory/roleAssignments
+    pub role_assignments_fps: Vec<String>,
+
+    pub other_user_attributes: Vec<String>,
+    pub other_group_attributes: Vec<String>,
+}
+
+const DEFAULT_USER_ATTRIBUTES: [&str; 9] = ["userPrincipalName", "mail", "userType", "employeeId", "onPremisesSyncEnabled",
+    "onPremisesSamAccountName", "onPremisesSecurityIdentifier", "onPremisesImmutableId", "onPremisesDistinguishedName"];
+const DEFAULT_GROUP_ATTRIBUTES: [&str; 8] = ["mail", "securityEnabled", "mailEnabled", "groupTypes", "isAssignableToRole",
+    "onPremisesSyncEnabled", "onPremisesSamAccountName", "onPremisesSecurityIdentifier"];
+
+impl EntraConnector {
+    pub fn load_entra_users(&self) -> Result<Vec<AccountDTO>> {
+        self._load_entra_users(&self._read_tenant()?, &mut Vec::new())
+    }
+    fn _load_entra_users(&self, tenant: &TenantData, diagnostics: &mut Vec<LoadDiagnostic>) -> Result<Vec<AccountDTO>> {
+        let users = json::read_paged_files(&self.users_fps, Paging::Graph)?;
+        for (fp, objects) in &users {
+            for (index, user) in objects.iter().enumerate() {
+                let diag = &mut ObjectDiagnostics { source: fp, object_index: index, diagnostics };
+                check_object(user, &["createdDateTime", "lastPasswordChangeDateTime", "signInActivity.lastSignInDateTime"],
+                    &["accountEnabled"], diag);
+            }
+        }
+        let mut accounts_memberof: HashMap<String, Vec<String>> = HashMap::new();
+        for m in tenant.memberships.iter().filter(|m| m.member_type != MemberType::Group) {
+            // Clones ok, new data being created
+            accounts_memberof.entry(m.member_id.clone()).or_default().push(m.entitlement_id.clone());
+        }
+
+        let accounts = users.iter()
+            .flat_map(|(fp, objects)| objects.iter().enumerate().map(|(index, user)| (SourcePosition::new(fp, index), user)))
+            .map(|(position, user)| {
+                let mut a = self._parse_user(user, position);
+                a.memberof = Some(accounts_memberof.remove(&a.unique_id).unwrap_or_default());
+                a
+            })
+            .collect();
+        Ok(accounts)
+    }
+    fn _parse_user(&self, user: &Map<String, Value>, position: SourcePosition) -> AccountDTO {
+        let unique_id = get_string(user, "id").unwrap_or("No ID".to_string()); // Preferable to continue with partial data
+        let display_name = get_string(user, "displayName").or_else(|| get_string(user, "userPrincipalName"));
+        let last_logon = user.get("signInActivity")
+            .and_then(|s| s.get("lastSignInDateTime"))
+            .and_then(Value::as_str)
+            .and_then(parse_iso8601);
+
+        AccountDTO {
+            unique_id,
+            alternate_ids: Vec::new(),
+            sids: Vec::new(),
+            display_name,
+            description: get_string(user, "jobTitle"),
+            created: get_date(user, "createdDateTime"),
+            last_logon,
+            password_last_set: get_date(user, "lastPasswordChangeDateTime"),
+            expiration_date: None,
+            enabled: user.get("accountEnabled").and_then(Value::as_bool),
+            deleted: user.get("deletedDateTime").map(|d| !d.is_null()),
+            locked: None,
+            flags: None,
+            memberof: None,
+            ou: None,
+            // Present when the export was made with $expand=manager
+            manager: user.get("manager").and_then(Value::as_object).and_then(|m| get_string(m, "id")),
+            other_attributes: other_attributes(user, &DEFAULT_USER_ATTRIBUTES, &self.other_user_attributes),
+            position: Some(position),
+        }
+    }
+
+    pub fn load_entra_groups(&self) -> Result<Vec<EntitlementDTO>> {
+        self._load_entra_groups(&self._read_tenant()?, &mut Vec::new())
+    }
+    fn _load_entra_groups(&self, tenant: &TenantData, diagnostics: &mut Vec<LoadDiagnostic>) -> Result<Vec<EntitlementDTO>> {
+        for (fp, objects) in &tenant.groups {
+            for (index, group) in objects.iter().enumerate() {
+                check_object(group, &["createdDateTime"], &[], &mut ObjectDiagnostics { source: fp, object_index: index, diagnostics });
+            }
+        }
+        let mut ents: Vec<EntitlementDTO> = tenant.groups.iter()
+            .flat_map(|(fp, objects)| objects.iter().enumerate().map(|(index, group)| (SourcePosition::new(fp, index), group)))
+            .map(|(position, group)| self._parse_group(group, position))
+            .collect();
+        ents.extend(EntraConnector::_parse_roles(tenant));
+
+        // Members of groups and roles
+        let mut members: HashMap<&str, Vec<String>> = HashMap::new();
+        let mut member_groups: HashMap<&str, Vec<String>> = HashMap::new();
+        for m in &tenant.memberships {
+            members.entry(&m.entitlement_id).or_default().push(m.member_id.clone());
+            if m.member_type == MemberType::Group {
+                member_groups.entry(&m.entitlement_id).or_default().push(m.member_id.clone());
+            }
+        }
+        for e in ents.iter_mut() {
+            e.members = Some(members.remove(e.unique_id.as_str()).unwrap_or_default());
+            e.member_groups = Some(member_groups.remove(e.unique_id.as_str()).unwrap_or_default());
+        }
+
+        // Post-processing (populate memberof and nested groups crawling)
+        nesting::calculate_memberof(&mut ents);
+        Ok(nesting::calculate_indirect_memberof(ents))
+    }
+    fn _parse_group(&self, group: &Map<String, Value>, position: SourcePosition) -> EntitlementDTO {
+        let ts_owners = group.get("owners")
+            .map(|owners| {
+                let mut objects = Vec::new();
+                flatten_pages(owners.clone(), Paging::Graph, &mut objects);
+                objects.iter().filter_map(|o| get_string(o, "id")).collect()
+            });
+
+        EntitlementDTO {
+            unique_id: get_string(group, "id").unwrap_or("No ID".to_string()), // Preferable to continue with partial data
+            alternate_ids: Vec::new(),
+            sids: Vec::new(),
+            display_name: get_string(group, "displayName"),
+            description: get_string(group, "description"),
+            created: get_date(group, "createdDateTime"),
+            memberof: None,
+            all_indirect_memberof: Vec::new(),
+            members: None,
+            member_groups: None,
+            ou: None,
+            other_attributes: other_attributes(group, &DEFAULT_GROUP_ATTRIBUTES, &self.other_group_attributes),
+            ts_owners,
+            position: Some(position),
+        }
+    }
+
+    // Directory roles become entitlements. Assignments restricted to a scope (e.g. an administrative unit)
+    // are a different entitlement than the tenant-wide role
+    fn _parse_roles(tenant: &TenantData) -> Vec<EntitlementDTO> {
+        let definitions: HashMap<String, &Map<String, Value>> = tenant.role_definitions.iter()
+            .filter_map(|d| get_string(d, "id").map(|id| (id, d)))
+            .collect();
+
+        let mut roles: HashMap<String, EntitlementDTO> = HashMap::new();
+        for assignment in &tenant.role_assignments {
+            let (role_uid, role_id, scope) = match EntraConnector::_role_uid(assignment) {
+                Some(r) => r,
+                None => continue,
+            };
+            if roles.contains_key(&role_uid) {
+                continue;
+            }
+            // Role definition from the definitions file, or expanded in the assignment
+            let definition = definitions.get(&role_id).copied()
+                .or_else(|| assignment.get("roleDefinition").and_then(Value::as_object));
+            let name = definition.and_then(|d| get_string(d, "displayName")).unwrap_or(role_id.clone());
+            let display_name = if scope == "/" { name } else { format!("{} ({})", name, scope) };
+
+            let mut other_attributes = HashMap::new();
+            other_attributes.insert("objectType".to_string(), Some("directoryRole".into()));
+            other_attributes.insert("directoryScopeId".to_string(), Some(scope.into()));
+            other_attributes.insert("isBuiltIn".to_string(), definition.and_then(|d| d.get("isBuiltIn")).and_then(AttributeValue::from_json));
+
+            roles.insert(role_uid.clone(), EntitlementDTO {
+                unique_id: role_uid,
+                alternate_ids: Vec::new(),
+                sids: Vec::new(),
+                display_name: Some(display_name),
+                description: definition.and_then(|d| get_string(d, "description")),
+                created: None,
+                memberof: None,
+                all_indirect_memberof: Vec::new(),
+                members: None,
+                member_groups: None,
+                ou: None,
+                other_attributes,
+                ts_owners: None,
+                position: None,
+            });
+        }
+        roles.into_values().collect()
+    }
+    fn _role_uid(assignment: &Map<String, Value>) -> Option<(String, String, String)> {
+        let role_id = get_string(assignment, "roleDefinitionId")?;
+        let scope = get_string(assignment, "directoryScopeId").unwrap_or("/".to_string());
+        let role_uid = if scope == "/" { role_id.clone() } else { format!("{}|{}", role_id, scope) };
+        Some((role_uid, role_id, scope))
+    }
+
+    // Files needed by both loads, read once per load
+    fn _read_tenant(&self) -> Result<TenantData<'_>> {
+        let groups = json::read_paged_files(&self.groups_fps, Paging::Graph)?;
+        let role_assignments = json::read_paged_objects(&self.role_assignments_fps, Paging::Graph)?;
+        let memberships = self._read_memberships(&groups, &role_assignments)?;
+        Ok(TenantData { groups, role_definitions: json::read_paged_objects(&self.role_definitions_fps, Paging::Graph)?, role_assignments, memberships })
+    }
+    // All (entitlement, member) links found in the group members files, expanded group members and role assignments
+    fn _read_memberships(&self, groups: &[PagedFile], role_assignments: &[Map<String, Value>]) -> Result<Vec<Membership>> {
+        let groups: Vec<&Map<String, Value>> = groups.iter().flat_map(|(_, objects)| objects).collect();
+        let group_ids: HashSet<String> = groups.iter().filter_map(|g| get_string(g, "id")).collect();
+
+        let mut memberships = Vec::new();
+
+        // Members expanded in the groups file
+        for group in groups {
+            if let (Some(group_id), Some(members)) = (get_string(group, "id"), group.get("members")) {
+                let mut objects = Vec::new();
+                flatten_pages(members.clone(), Paging::Graph, &mut objects);
+                memberships.extend(objects.iter().filter_map(|o| Membership::from(&group_id, o, &group_ids)));
+            }
+        }
+
+        // Members files, keyed by group id
+        for fp in &self.group_members_fps {
+            let content = read_json(fp)?;
+            let per_group = content.as_object()
+                .ok_or_else(|| anyhow!("Group members file is not an object keyed by group id: {}", fp))?;
+            for (group_id, pages) in per_group {
+                let mut objects = Vec::new();
+                flatten_pages(pages.clone(), Paging::Graph, &mut objects);
+                memberships.extend(objects.iter().filter_map(|o| Membership::from(group_id, o, &group_ids)));
+            }
+        }
+
+        // Role assignments
+        for assignment in role_assignments {
+            if let (Some((role_uid, _, _)), Some(principal_id)) = (EntraConnector::_role_uid(assignment), get_string(assignment, "principalId")) {
+                let member_type = if group_ids.contains(&principal_id) { MemberType::Group } else { MemberType::Other };
+                memberships.push(Membership { entitlement_id: role_uid, member_id: principal_id, member_type });
+            }
+        }
+
+        // The same link can be found in more than one file
+        let mut seen = HashSet::new();
+        memberships.retain(|m| seen.insert((m.entitlement_id.clone(), m.member_id.clone())));
+        Ok(memberships)
+    }
+}
+impl TargetSystemConnector for EntraConnector {
+    fn load_accounts(&self) -> Result<Vec<AccountDTO>> {
+        self.load_entra_users()
+    }
+    fn load_entitlements(&self) -> Result<Vec<EntitlementDTO>> {
+        self.load_entra_groups()
+    }
+    fn load_accounts_with_diagnostics(&self, diagnostics: &mut Vec<LoadDiagnostic>) -> Result<Vec<AccountDTO>> {
+        self._load_entra_users(&self._read_tenant()?, diagnostics)
+    }
+    fn load_entitlements_with_diagnostics(&self, diagnostics: &mut Vec<LoadDiagnostic>) -> Result<Vec<EntitlementDTO>> {
+        self._load_entra_groups(&self._read_tenant()?, diagnostics)
+    }
+    // Groups, roles and memberships are needed by both loads, they are read once
+    fn load_all_with_diagnostics(&self, diagnostics: &mut Vec<LoadDiagnostic>) -> Result<(Vec<AccountDTO>, Vec<EntitlementDTO>)> {
+        let tenant = self._read_tenant()?;
+        Ok((self._load_entra_users(&tenant, diagnostics)?, self._load_entra_groups(&tenant, diagnostics)?))
+    }
+}
+
+// Groups, roles and the memberships found in them
+struct TenantData<'a> {
+    groups: Vec<PagedFile<'a>>,
+    role_definitions: Vec<Map<String, Value>>,
+    role_assignments: Vec<Map<String, Value>>,
+    memberships: Vec<Membership>,
+}
+
+#[derive(Debug, PartialEq)]
+enum MemberType {
+    Group,
+    Other,
+}
+
+struct Membership {
+    entitlement_id: String,
+    member_id: String,
+    member_type: MemberType,
+}
+impl Membership {
+    fn from(entitlement_id: &str, member: &Map<String, Value>, group_ids: &HashSet<String>) -> Option<Self> {
+        let member_id = get_string(member, "id")?;
+        let is_group = member.get("@odata.type").and_then(Value::as_str) == Some("#microsoft.graph.group")
+            || group_ids.contains(&member_id);
+        Some(Membership {
+            entitlement_id: entitlement_id.to_string(),
+            member_id,
+            member_type: if is_group { MemberType::Group } else { MemberType::Other },
+        })
+    }
+}
+
+// Reports the values the mapping drops: missing id, dates that aren't ISO 8601, flags that aren't booleans.
+// Nested values are given as paths ("signInActivity.lastSignInDateTime")
+fn check_object(object: &Map<String, Value>, dates: &[&str], bools: &[&str], diag: &mut ObjectDiagnostics) {
+    if get_string(object, "id").is_none() {
+        diag.error("id", "Missing id, loaded as \"No ID\"");
+    }
+    let lookup = |path: &str| {
+        let mut keys = path.split('.');
+        let first = object.get(keys.next()?)?;
+        keys.try_fold(first, |value, key| value.get(key)).filter(|v| !v.is_null())
+    };
+    for date in dates {
+        if let Some(value) = lookup(date) {
+            if value.as_str().and_then(parse_iso8601).is_none() {
+                diag.warning(date, &format!("Unparseable date {}, ignored", value));
+            }
+        }
+    }
+    for flag in bools {
+        if let Some(value) = lookup(flag).filter(|v| !v.is_boolean()) {
+            diag.warning(flag, &format!("{} is not a boolean, ignored", value));
+        }
+    }
+}
+
+fn other_attributes(object: &Map<String, Value>, defaults: &[&str], configured: &[String]) -> HashMap<String, Option<AttributeValue>> {
+    defaults.iter()
+        .map(|k| k.to_string())
+        .chain(configured.iter().cloned())
+        .map(|k| {
+            let v = object.get(&k).and_then(AttributeValue::from_json);
+            (k, v)
+        })
+        .collect()
+}
@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+use chrono::NaiveDate;
+use serde::Serialize;
+use anyhow::{Result, anyhow};
+
+use super::csv::{self, CsvOptions};
+use super::dtos::{IdentityDTO, StatusMapping};
+use super::traits::IdentitySourceConnector;
+use super::diagnostics::{LoadDiagnostic, ObjectDiagnostics};
+
+#[derive(Debug, Serialize)]
+pub struct IdentityCsvConnector {
+    pub source_path: String,
+    pub options: CsvOptions,
+    // chrono format of hire and termination dates (e.g. "%Y-%m-%d", "%d/%m/%Y")
+    pub date_format: String,
+    // Values of the status column
+    pub status_mapping: StatusMapping,
+
+    pub identity_attributes: Vec<CsvColumnMeaning>,
+}
+
+// Same idea as ColumnMeaning for Xlsx: columns are found by header name.
+// Columns without attribute are stored in the free-form attributes of the DTO
+#[derive(Debug, Clone, Serialize)]
+pub struct CsvColumnMeaning {
+    pub csv_column_name: String,
+    pub attribute: Option<String>,
+}
+
+impl IdentityCsvConnector {
+    pub fn read_identities(&self) -> Result<Vec<IdentityDTO>> {
+        self._read_identities(&mut Vec::new())
+    }
+    fn _read_identities(&self, diagnostics: &mut Vec<LoadDiagnostic>) -> Result<Vec<IdentityDTO>> {
+        let rows: Vec<(usize, HashMap<String, String>)> = csv::read_numbered_with_options(&self.source_path, &self.options)?;
+
+        rows.into_iter()
+            .map(|(line, row)| self._extract_identity(row,
+                &mut ObjectDiagnostics { source: &self.source_path, object_index: line, diagnostics }))
+            .collect()
+    }
+    fn _extract_identity(&self, mut row: HashMap<String, String>, diag: &mut ObjectDiagnostics) -> Result<IdentityDTO> {
+        let mut identity = IdentityDTO { position: Some(diag.position()), ..Default::default() };
+
+        // For each attribute configured to be read, it retrieves the data by column name
+        // and stores it in the IdentityDTO property indicated in the configuration
+        for column_meaning in &self.identity_attributes {
+            let value = row.remove(&column_meaning.csv_column_name)
+                .ok_or_else(|| anyhow!("Column name '{}' not found in the header of {}", column_meaning.csv_column_name, self.source_path))?;
+
+            match &column_meaning.attribute {
+                None => {identity.attributes.insert(column_meaning.csv_column_name.clone(), value);}
+                Some(field) => {
+                    match field.as_str() {
+                        "unique_id" => {
+                            if value.trim().is_empty() {
+                                diag.error(field, "Empty unique ID");
+                            }
+                            identity.unique_id = value.to_uppercase()
+                        }
+                        "first_name" => identity.first_name = value,
+                        "last_name" => identity.last_name = value,
+                        "email" => identity.email = value,
+                        "employee_no" => identity.employee_no = value,
+                        "employee_type" => identity.employee_type = value,
+                        "status" | "enabled" => {
+                            identity.status = self.status_mapping.get(&value);
+                            if identity.status.is_none() && !value.trim().is_empty() {
+                                diag.warning(field, &format!("Unknown status '{}', ignored", value));
+                            }
+                        }
+                        "manager_key" => identity.manager_key = value,
+                        "hire_date" => identity.hire_date = self._read_date(&value, field, diag),
+                        "termination_date" => identity.termination_date = self._read_date(&value, field, diag),
+                        _ => {}
+                    }
+                }
+            }
+        }
+        Ok(identity)
+    }
+    fn _read_date(&self, value: &str, field: &str, diag: &mut ObjectDiagnostics) -> Option<NaiveDate> {
+        let date = NaiveDate::parse_from_str(value.trim(), &self.date_format).ok();
+        if date.is_none() && !value.trim().is_empty() {
+            diag.warning(field, &format!("Date '{}' doesn't match the format '{}', ignored", value, self.date_format));
+        }
+        date
+    }
+}
+impl IdentitySourceConnector for IdentityCsvConnector {
+    fn read_identities(&self) -> Result<Vec<IdentityDTO>> {
+        IdentityCsvConnector::read_identities(self)
+    }
+    fn read_identities_with_diagnostics(&self, diagnostics: &mut Vec<LoadDiagnostic>) -> Result<Vec<IdentityDTO>> {
+        self._read_identities(diagnostics)
+    }
+}
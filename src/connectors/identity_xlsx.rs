@@ -1,109 +1,208 @@
-use std::sync::RwLock;
-use calamine::{Reader, Xlsx, open_workbook, DataType};
+use calamine::{Reader, Range, open_workbook_auto, DataType};
 use chrono::Duration;
 use chrono::NaiveDate;
 use serde::Serialize;
 use anyhow::Result;
 use anyhow::Context; 
 
-use super::dtos::IdentityDTO;
+use super::dtos::{IdentityDTO, StatusMapping};
+use super::traits::IdentitySourceConnector;
+use super::diagnostics::{LoadDiagnostic, ObjectDiagnostics};
 
 #[derive(Debug, Serialize)]
 pub struct IdentityXlsxConnector { 
+    // Any format calamine reads: xlsx, xlsm, xlsb, xls, ods
     pub source_path: String,
-    pub sheet_name: String,
+    // Sheets read one after the other, with the same columns. '*' matches any text (e.g. "HR *")
+    pub sheet_names: Vec<String>,
+    pub header_row: HeaderRow,
+    // Values of the status column, text or numbers
+    pub status_mapping: StatusMapping,
+    // Formats of dates stored as text, tried in order (e.g. "%d/%m/%Y"). Date cells don't need one
+    pub date_formats: Vec<String>,
 
-    pub identity_attributes: RwLock<Vec<ColumnMeaning>>, 
+    pub identity_attributes: Vec<ColumnMeaning>,
 }
 
-#[derive(Debug, Serialize)]
+// Where the column names are, in each sheet
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub enum HeaderRow {
+    // First row containing all the configured column names, title rows above it are skipped
+    #[default]
+    Auto,
+    // Row number shown by Excel (1 based)
+    Row(usize),
+}
+
+// Rows searched for the header by HeaderRow::Auto
+const HEADER_SEARCH_ROWS: usize = 20;
+
+#[derive(Debug, Clone, Serialize)]
 pub struct ColumnMeaning {
     pub xlsx_column_name: String,
     pub identity_attribute: Option<String>,
-    pub column_index: usize,
+}
+
+// Position of each configured column in the header of one sheet. Built for each sheet read,
+// the connector itself is never modified, so it can read several files at once
+struct ColumnPlan<'a> {
+    columns: Vec<(usize, &'a ColumnMeaning)>,
+}
+impl ColumnPlan<'_> {
+    // Every configured cell is empty. Cell errors are kept, they're reported
+    fn is_blank(&self, row: &[DataType]) -> bool {
+        self.columns.iter().all(|&(i, _)| match row.get(i) {
+            None => true,
+            Some(DataType::Error(_)) => false,
+            Some(cell) => IdentityXlsxConnector::_read_text(cell).is_empty(),
+        })
+    }
 }
 impl IdentityXlsxConnector {
     pub fn read_identities(&self) -> Result<Vec<IdentityDTO>>{
+        self._read_identities(&mut Vec::new())
+    }
+    fn _read_identities(&self, diagnostics: &mut Vec<LoadDiagnostic>) -> Result<Vec<IdentityDTO>>{
         let mut identities = Vec::new();
     
-        // Open excel file
-        let mut excel: Xlsx<_> = open_workbook(&self.source_path).context("Failed to open the workbook")?; 
-
-        // Open sheet indicated in configuration
-        if let Some(Ok(r)) = excel.worksheet_range(&self.sheet_name) {
-
-            // If there is at least one row, it proceeds to load identities
-            if let Some(first_row) = r.rows().next() {
-
-                // Goes through the header row and records the index of each column name
-                // This is necessary to retrieve data rows later
-                self._load_column_indexes(first_row)?;
+        // Open workbook, the format is given by the extension
+        let mut workbook = open_workbook_auto(&self.source_path)
+            .with_context(|| format!("Failed to open the workbook: {}", self.source_path))?; 
 
-                // Loads one identity per row, skipping the header
-                for row in r.rows().skip(1) {
-                    let identity = self._extract_identity(row)?;
-                    identities.push(identity);
-                }
+        for sheet_name in self._select_sheets(&workbook.sheet_names(), diagnostics) {
+            match workbook.worksheet_range(&sheet_name) {
+                Some(Ok(r)) => self._read_sheet(&r, &sheet_name, &mut identities, diagnostics)?,
+                _ => diagnostics.push(LoadDiagnostic::error(&self.source_path, None, None,
+                    &format!("Sheet '{}' unreadable, no identity loaded from it", sheet_name))),
             }
-        } 
+        }
         Ok(identities)
     }
-    fn _load_column_indexes(&self, first_row: &[DataType]) -> Result<()> {
-        
-        // Goes through the header row and records the index of each column name
-        let mut rww_guard = match self.identity_attributes.write() {
-            Ok(guard) => guard,
-            Err(_) => {
-                return Err(anyhow::Error::msg("Failed to acquire write lock on IdentityXlsxConnector"));
-            },
+    // Sheets matching the configuration, in workbook order, each one once
+    fn _select_sheets(&self, workbook_sheets: &[String], diagnostics: &mut Vec<LoadDiagnostic>) -> Vec<String> {
+        let mut selected: Vec<String> = Vec::new();
+        for pattern in &self.sheet_names {
+            let matching: Vec<&String> = workbook_sheets.iter().filter(|name| IdentityXlsxConnector::_matches(pattern, name)).collect();
+            if matching.is_empty() {
+                diagnostics.push(LoadDiagnostic::error(&self.source_path, None, None,
+                    &format!("Sheet '{}' not found, no identity loaded from it", pattern)));
+            }
+            for name in matching {
+                if !selected.contains(name) {
+                    selected.push(name.clone());
+                }
+            }
+        }
+        selected.sort_by_key(|name| workbook_sheets.iter().position(|n| n == name));
+        selected
+    }
+    // Sheet name matching, '*' is the only wildcard
+    fn _matches(pattern: &str, name: &str) -> bool {
+        let mut parts = pattern.split('*');
+        let first = parts.next().unwrap_or_default();
+        let Some(mut rest) = name.strip_prefix(first) else {
+            return false;
+        };
+        let parts: Vec<&str> = parts.collect();
+        let Some((last, middle)) = parts.split_last() else {
+            // No wildcard
+            return rest.is_empty();
         };
-        for column_meaning  in &mut *rww_guard {
-            column_meaning.column_index = first_row.iter().position(|cell| cell == column_meaning.xlsx_column_name.as_str())
-                .ok_or_else(|| {
-                    anyhow::anyhow!("Column name '{}' not found in the first row of Xlsx", column_meaning.xlsx_column_name)
-                })?;
+        for part in middle {
+            match rest.find(part) {
+                Some(i) => rest = &rest[i + part.len()..],
+                None => return false,
+            }
         }
-        Ok(())
+        rest.len() >= last.len() && rest.ends_with(last)
     }
-    fn _extract_identity(&self, row: &[DataType]) -> Result<IdentityDTO> {
+    fn _read_sheet(&self, r: &Range<DataType>, sheet_name: &str, identities: &mut Vec<IdentityDTO>, diagnostics: &mut Vec<LoadDiagnostic>) -> Result<()> {
+        // Problems are reported with the row number shown by Excel (1 based), the range can start below row 1
+        let source = format!("{} [{}]", self.source_path, sheet_name);
+        let first_row_number = r.start().map_or(1, |(row, _)| row as usize + 1);
+
+        // Empty sheet
+        if r.rows().next().is_none() {
+            return Ok(());
+        }
 
-        // Get read permissions on attributes to retrieve from the rows
-        let rwr_guard = match self.identity_attributes.read() {
-            Ok(guard) => guard,
-            Err(_) => {
-                return Err(anyhow::Error::msg("Failed to acquire read lock on IdentityXlsxConnector"));
-            },
+        let header_index = match self.header_row {
+            HeaderRow::Auto => r.rows().take(HEADER_SEARCH_ROWS).position(|row| self._is_header(row))
+                .ok_or_else(|| anyhow::anyhow!("No row with all the configured column names in the first {} rows of {}", HEADER_SEARCH_ROWS, source))?,
+            HeaderRow::Row(number) => number.checked_sub(first_row_number)
+                .ok_or_else(|| anyhow::anyhow!("Header row {} is above the first row with data ({}) of {}", number, first_row_number, source))?,
         };
+        let header = r.rows().nth(header_index)
+            .ok_or_else(|| anyhow::anyhow!("Header row {} is after the last row of {}", first_row_number + header_index, source))?;
+
+        // Goes through the header row and records the index of each column name
+        // This is necessary to retrieve data rows later
+        let plan = self._plan_columns(header)
+            .with_context(|| format!("Wrong header in {}", source))?;
 
-        let mut identity = IdentityDTO::default();
+        // Loads one identity per row, after the header. Blank rows (formatted, or left by deletions) are skipped
+        for (index, row) in r.rows().enumerate().skip(header_index + 1) {
+            if plan.is_blank(row) {
+                continue;
+            }
+            let identity = self._extract_identity(&plan, row,
+                &mut ObjectDiagnostics { source: &source, object_index: first_row_number + index, diagnostics })?;
+            identities.push(identity);
+        }
+        Ok(())
+    }
+    fn _is_header(&self, row: &[DataType]) -> bool {
+        self.identity_attributes.iter().all(|column_meaning| row.iter()
+            .any(|cell| IdentityXlsxConnector::_read_text(cell) == column_meaning.xlsx_column_name))
+    }
+    fn _plan_columns(&self, header: &[DataType]) -> Result<ColumnPlan<'_>> {
+        // Goes through the header row and records the index of each column name
+        let columns = self.identity_attributes.iter()
+            .map(|column_meaning| {
+                header.iter().position(|cell| IdentityXlsxConnector::_read_text(cell) == column_meaning.xlsx_column_name)
+                    .map(|i| (i, column_meaning))
+                    .ok_or_else(|| anyhow::anyhow!("Column name '{}' not found in the header row", column_meaning.xlsx_column_name))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(ColumnPlan { columns })
+    }
+    fn _extract_identity(&self, plan: &ColumnPlan, row: &[DataType], diag: &mut ObjectDiagnostics) -> Result<IdentityDTO> {
+        let mut identity = IdentityDTO { position: Some(diag.position()), ..Default::default() };
 
         // For each attribute configured to be read, it gets the column index, retrieves the data
         // and stores it in the IdentityDTO property indicated in the configuration
-        for column_meaning in & *rwr_guard { 
-            let i = column_meaning.column_index;
+        for &(i, column_meaning) in &plan.columns {
+            // Rows can be shorter than the header when the last cells are empty
+            let cell = row.get(i).unwrap_or(&DataType::Empty);
+            let attribute = column_meaning.identity_attribute.as_deref().unwrap_or(&column_meaning.xlsx_column_name);
+            if let DataType::Error(e) = cell {
+                diag.warning(attribute, &format!("Cell error '{}', read as empty", e));
+            }
             match &column_meaning.identity_attribute {
-                None => {identity.attributes.insert(column_meaning.xlsx_column_name.clone(), row[i].to_string());}
+                None => {identity.attributes.insert(column_meaning.xlsx_column_name.clone(), IdentityXlsxConnector::_read_text(cell));}
                 Some(field) => {
                     match field.as_str() {
-                        "unique_id" => identity.unique_id = row[i].to_string().to_uppercase(),
-                        "first_name" => identity.first_name = row[i].to_string(),
-                        "last_name" => identity.last_name = row[i].to_string(),
-                        "email" => identity.email = row[i].to_string(),
-                        "employee_no" => identity.employee_no = row[i].to_string(),
-                        "employee_type" => identity.employee_type = row[i].to_string(),
-                        "enabled" => {
-                            match row[i].as_i64() { 
-                                Some(inactive) => {
-                                    if inactive == 0 { identity.enabled = Some(false) }
-                                    else if inactive == 3 { identity.enabled = Some(true) }
-                                    else {identity.enabled = None}
-                                }
-                                None => {identity.enabled = None}
+                        "unique_id" => {
+                            identity.unique_id = IdentityXlsxConnector::_read_text(cell).to_uppercase();
+                            if identity.unique_id.is_empty() {
+                                diag.error(field, "Empty unique ID");
+                            }
+                        }
+                        "first_name" => identity.first_name = IdentityXlsxConnector::_read_text(cell),
+                        "last_name" => identity.last_name = IdentityXlsxConnector::_read_text(cell),
+                        "email" => identity.email = IdentityXlsxConnector::_read_text(cell),
+                        "employee_no" => identity.employee_no = IdentityXlsxConnector::_read_text(cell),
+                        "employee_type" => identity.employee_type = IdentityXlsxConnector::_read_text(cell),
+                        "status" | "enabled" => {
+                            let value = IdentityXlsxConnector::_read_text(cell);
+                            identity.status = self.status_mapping.get(&value);
+                            if identity.status.is_none() && !value.is_empty() {
+                                diag.warning(field, &format!("Unknown status '{}', ignored", value));
                             }
                         }
-                        "manager_key" => identity.manager_key = row[i].to_string(),
-                        "hire_date" => identity.hire_date = IdentityXlsxConnector::_read_date(&row[i]),
-                        "termination_date" => identity.termination_date = IdentityXlsxConnector::_read_date(&row[i]),
+                        "manager_key" => identity.manager_key = IdentityXlsxConnector::_read_text(cell),
+                        "hire_date" => identity.hire_date = self._read_date(cell, field, diag),
+                        "termination_date" => identity.termination_date = self._read_date(cell, field, diag),
                         _ => {}
                     }
                 }
@@ -112,14 +211,51 @@ impl IdentityXlsxConnector {
         Ok(identity)
     }
 
-    fn _read_date(cell: &DataType) -> Option<NaiveDate> {
-        if let DataType::DateTime(serial) = cell {
-            let start = NaiveDate::from_ymd_opt(1899, 12, 30).expect("Creating date that certainly exists");
-            start.checked_add_signed(Duration::days(*serial as i64))
-        } else {
-            None
+    // Text of a cell as typed in Excel: whole numbers without decimals (IDs, employee numbers), dates as yyyy-mm-dd
+    fn _read_text(cell: &DataType) -> String {
+        match cell {
+            DataType::Float(n) if n.fract() == 0.0 && n.abs() < 1e15 => (*n as i64).to_string(),
+            DataType::String(s) => s.trim().to_string(),
+            DataType::DateTime(serial) => IdentityXlsxConnector::_from_serial(*serial).map(|d| d.to_string()).unwrap_or_default(),
+            DataType::Error(_) | DataType::Empty => String::new(),
+            _ => cell.to_string(),
+        }
+    }
+
+    // Accepts date cells, numbers (date cells without date format) and text in one of the configured formats
+    fn _read_date(&self, cell: &DataType, field: &str, diag: &mut ObjectDiagnostics) -> Option<NaiveDate> {
+        let date = match cell {
+            DataType::DateTime(serial) | DataType::Float(serial) => IdentityXlsxConnector::_from_serial(*serial),
+            DataType::Int(serial) => IdentityXlsxConnector::_from_serial(*serial as f64),
+            // ISO dates of the OpenDocument formats, time is ignored
+            DataType::DateTimeIso(iso) => iso.get(..10).and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok()),
+            DataType::String(text) => self.date_formats.iter()
+                .find_map(|format| NaiveDate::parse_from_str(text.trim(), format).ok()),
+            _ => None,
+        };
+        if date.is_none() && !cell.is_empty() && !matches!(cell, DataType::Error(_)) {
+            match cell {
+                DataType::String(_) => diag.warning(field, &format!("Date '{}' doesn't match the formats {:?}, ignored", cell, self.date_formats)),
+                _ => diag.warning(field, &format!("'{}' is not a date, ignored", cell)),
+            }
         }
+        date
+    }
+    fn _from_serial(serial: f64) -> Option<NaiveDate> {
+        let start = NaiveDate::from_ymd_opt(1899, 12, 30).expect("Creating date that certainly exists");
+        // Negative or after 9999-12-31
+        if !(0.0..=2958465.0).contains(&serial) {
+            return None;
+        }
+        start.checked_add_signed(Duration::days(serial.trunc() as i64))
     }
    
 }
-
+impl IdentitySourceConnector for IdentityXlsxConnector {
+    fn read_identities(&self) -> Result<Vec<IdentityDTO>> {
+        IdentityXlsxConnector::read_identities(self)
+    }
+    fn read_identities_with_diagnostics(&self, diagnostics: &mut Vec<LoadDiagnostic>) -> Result<Vec<IdentityDTO>> {
+        self._read_identities(diagnostics)
+    }
+}
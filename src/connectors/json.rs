@@ -0,0 +1,205 @@
+use std::{fs, io, fmt, marker::PhantomData, collections::HashMap};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use encoding_rs::{Decoder, Encoding, UTF_8, UTF_16BE, UTF_16LE};
+use serde::Deserialize;
+use serde::de::{Deserializer, MapAccess, SeqAccess, Visitor, value::MapAccessDeserializer};
+use serde_json::{Map, Value};
+use chrono::{DateTime, FixedOffset};
+use anyhow::{Result, Context};
+
+use super::timestamps::parse_iso8601;
+
+// Reads a whole JSON file. UTF-8 and UTF-16 byte order marks are tolerated
+pub fn read_json(fp: &str) -> Result<Value> {
+    let bytes = fs::read(fp)
+        .with_context(|| format!("Failed to read file: {}", fp))?;
+    let encoding = Encoding::for_bom(&bytes).map_or(UTF_8, |(e, _)| e);
+    let (file_content, _) = encoding.decode_with_bom_removal(&bytes);
+    serde_json::from_str(&file_content)
+        .with_context(|| format!("Failed to deserialize json after opening file: {}", fp))
+}
+
+// Reads a JSON array of objects one element at a time, mapping each object as soon as it's parsed.
+// Only the mapped values are kept, the JSON tree of the file is never held in memory.
+// A single object without the array (ConvertTo-Json with one result) is accepted, and so are UTF-16 files
+// (Out-File and ">" in Windows PowerShell)
+pub fn stream_json_objects<T, F>(fp: &str, map: F) -> Result<Vec<T>>
+where
+    F: FnMut(HashMap<String, Value>) -> T,
+{
+    let reader = open_decoded(fp)?;
+
+    let mut de = serde_json::Deserializer::from_reader(reader);
+    let mapped = de.deserialize_any(MappingVisitor { map, marker: PhantomData })
+        .and_then(|mapped| de.end().map(|_| mapped))
+        .with_context(|| format!("Failed to deserialize json after opening file: {}", fp))?;
+    Ok(mapped)
+}
+
+// Opens a text file as UTF-8. UTF-16 files (with byte order mark) are decoded on the fly, a UTF-8 byte order mark is skipped
+pub fn open_decoded(fp: &str) -> Result<Box<dyn Read>> {
+    let file = File::open(fp)
+        .with_context(|| format!("Failed to read file: {}", fp))?;
+    let mut reader = BufReader::new(file);
+
+    // Byte order mark
+    let buf = reader.fill_buf()
+        .with_context(|| format!("Failed to read file: {}", fp))?;
+    let utf16 = if buf.starts_with(&[0xFF, 0xFE]) {
+        Some(UTF_16LE)
+    } else if buf.starts_with(&[0xFE, 0xFF]) {
+        Some(UTF_16BE)
+    } else {
+        if buf.starts_with(&[0xEF, 0xBB, 0xBF]) {
+            reader.consume(3);
+        }
+        None
+    };
+    let reader: Box<dyn Read> = match utf16 {
+        Some(encoding) => {
+            reader.consume(2);
+            // Decoded to UTF-8 on the fly, buffered as serde_json reads byte by byte
+            Box::new(BufReader::new(DecodingReader::new(reader, encoding.new_decoder_without_bom_handling())))
+        }
+        None => Box::new(reader),
+    };
+    Ok(reader)
+}
+
+// Paged responses of the APIs the exports come from
+#[derive(Debug, Clone, Copy)]
+pub enum Paging {
+    // Microsoft Graph: the objects of a page are in "value", next to the @odata properties
+    Graph,
+    // SCIM ListResponse: the resources are in "Resources"
+    ScimListResponse,
+}
+impl Paging {
+    fn list_key(self) -> &'static str {
+        match self {
+            Paging::Graph => "value",
+            Paging::ScimListResponse => "Resources",
+        }
+    }
+    fn is_page(self, map: &Map<String, Value>) -> bool {
+        match self {
+            Paging::Graph => map.get("value").is_some_and(Value::is_array)
+                && (map.len() == 1 || map.keys().any(|k| k.starts_with("@odata"))),
+            // Empty ListResponses have no Resources
+            Paging::ScimListResponse => map.contains_key("Resources") || map.contains_key("totalResults"),
+        }
+    }
+}
+
+// Path of a file and its objects
+pub type PagedFile<'a> = (&'a str, Vec<Map<String, Value>>);
+
+// Reads and concatenates all objects of all pages of all files
+pub fn read_paged_objects(fps: &[String], paging: Paging) -> Result<Vec<Map<String, Value>>> {
+    Ok(read_paged_files(fps, paging)?.into_iter().flat_map(|(_, objects)| objects).collect())
+}
+// Objects of all pages of each file
+pub fn read_paged_files(fps: &[String], paging: Paging) -> Result<Vec<PagedFile<'_>>> {
+    let mut files = Vec::new();
+    for fp in fps {
+        let mut objects = Vec::new();
+        flatten_pages(read_json(fp)?, paging, &mut objects);
+        files.push((fp.as_str(), objects));
+    }
+    Ok(files)
+}
+
+// Pages can be nested (arrays of pages, pages inside pages), the objects are collected in order
+pub fn flatten_pages(value: Value, paging: Paging, objects: &mut Vec<Map<String, Value>>) {
+    match value {
+        Value::Array(items) => {
+            for item in items {
+                flatten_pages(item, paging, objects);
+            }
+        }
+        Value::Object(mut map) => {
+            if paging.is_page(&map) {
+                if let Some(items) = map.remove(paging.list_key()) {
+                    flatten_pages(items, paging, objects);
+                }
+            } else {
+                objects.push(map);
+            }
+        }
+        _ => {}
+    }
+}
+
+// Scalars are converted to text, arrays are joined
+pub fn get_string(object: &Map<String, Value>, key: &str) -> Option<String> {
+    match object.get(key)? {
+        Value::String(s) => Some(s.clone()),
+        Value::Bool(b) => Some(b.to_string()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Array(values) => Some(values.iter().filter_map(Value::as_str).collect::<Vec<&str>>().join(", ")),
+        _ => None,
+    }
+}
+// ISO 8601 text
+pub fn get_date(object: &Map<String, Value>, key: &str) -> Option<DateTime<FixedOffset>> {
+    object.get(key).and_then(Value::as_str).and_then(parse_iso8601)
+}
+
+// Converts a stream in another encoding into UTF-8
+struct DecodingReader<R> {
+    inner: R,
+    decoder: Decoder,
+    input: Vec<u8>,
+    start: usize,
+    end: usize,
+    eof: bool,
+}
+impl<R: Read> DecodingReader<R> {
+    fn new(inner: R, decoder: Decoder) -> Self {
+        Self { inner, decoder, input: vec![0; 8192], start: 0, end: 0, eof: false }
+    }
+}
+impl<R: Read> Read for DecodingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.start == self.end && !self.eof {
+                self.end = self.inner.read(&mut self.input)?;
+                self.start = 0;
+                self.eof = self.end == 0;
+            }
+            let (_, read, written, _) = self.decoder.decode_to_utf8(&self.input[self.start..self.end], buf, self.eof);
+            self.start += read;
+            // Nothing written yet when the input ended in the middle of a character
+            if written > 0 || self.eof {
+                return Ok(written);
+            }
+        }
+    }
+}
+
+struct MappingVisitor<T, F> {
+    map: F,
+    marker: PhantomData<T>,
+}
+impl<'de, T, F> Visitor<'de> for MappingVisitor<T, F>
+where
+    F: FnMut(HashMap<String, Value>) -> T,
+{
+    type Value = Vec<T>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an array of objects, or one object")
+    }
+    fn visit_map<A: MapAccess<'de>>(mut self, map: A) -> Result<Vec<T>, A::Error> {
+        let object = HashMap::<String, Value>::deserialize(MapAccessDeserializer::new(map))?;
+        Ok(vec![(self.map)(object)])
+    }
+    fn visit_seq<A: SeqAccess<'de>>(mut self, mut seq: A) -> Result<Vec<T>, A::Error> {
+        let mut mapped = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(object) = seq.next_element::<HashMap<String, Value>>()? {
+            mapped.push((self.map)(object));
+        }
+        Ok(mapped)
+    }
+}
@@ -0,0 +1,341 @@
+use std::{collections::HashMap, io::Read};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use serde_json::Value;
+use anyhow::{Result, Context, anyhow};
+
+use super::ad::{AdUserAttributes, AdGroupAttributes};
+use super::dtos::{AccountDTO, EntitlementDTO};
+use super::traits::TargetSystemConnector;
+use super::diagnostics::{LoadDiagnostic, ObjectDiagnostics};
+use super::json::open_decoded;
+
+// Offline directory exports in LDIF format (ldifde, ldapsearch).
+// Users and groups can be in the same or in separate files, objects are told apart by their objectClass.
+// The same attribute mappings as ADConnector are used, so a target system config works for both formats
+#[derive(Debug, Clone)]
+pub struct LdifConnector {
+    pub ldif_fps: Vec<String>,
+
+    pub ad_user_attributes: AdUserAttributes,
+    pub ad_group_attributes: AdGroupAttributes,
+}
+// Path of a file and its entries
+type LdifFile<'a> = (&'a str, Vec<HashMap<String, Value>>);
+
+impl LdifConnector {
+    pub fn load_ldif_users(&self) -> Result<Vec<AccountDTO>> {
+        Ok(self._load_ldif_users(&self._read_entries()?, &mut Vec::new()))
+    }
+    fn _load_ldif_users(&self, files: &[LdifFile], diagnostics: &mut Vec<LoadDiagnostic>) -> Vec<AccountDTO> {
+        let mut accounts = Vec::new();
+        let mapped = self.ad_user_attributes.mapped_attributes();
+        for (fp, entries) in files {
+            for (index, entry) in entries.iter().enumerate() {
+                // Reported once, with the accounts: the groups skip the same entries
+                if LdifConnector::_object_classes(entry).is_empty() {
+                    diagnostics.push(LoadDiagnostic::warning(fp, Some(index), Some("objectClass"),
+                        "Entry without objectClass, neither an account nor a group: ignored"));
+                    continue;
+                }
+                if !LdifConnector::_is_user(entry) {
+                    continue;
+                }
+                let diag = &mut ObjectDiagnostics { source: fp, object_index: index, diagnostics };
+                self.ad_user_attributes.diagnose(entry, &mapped, diag);
+                accounts.push(AccountDTO { position: Some(diag.position()), ..self.ad_user_attributes.to_account_dto(entry) });
+            }
+        }
+        accounts
+    }
+    pub fn load_ldif_groups(&self) -> Result<Vec<EntitlementDTO>> {
+        Ok(self._load_ldif_groups(&self._read_entries()?, &mut Vec::new()))
+    }
+    fn _load_ldif_groups(&self, files: &[LdifFile], diagnostics: &mut Vec<LoadDiagnostic>) -> Vec<EntitlementDTO> {
+        let mut ents = Vec::new();
+        let mapped = self.ad_group_attributes.mapped_attributes();
+        for (fp, entries) in files {
+            for (index, entry) in entries.iter().enumerate().filter(|(_, entry)| LdifConnector::_is_group(entry)) {
+                let diag = &mut ObjectDiagnostics { source: fp, object_index: index, diagnostics };
+                self.ad_group_attributes.diagnose(entry, &mapped, diag);
+                ents.push(EntitlementDTO { position: Some(diag.position()), ..self.ad_group_attributes.to_entitlement_dto(entry) });
+            }
+        }
+        self.ad_group_attributes.post_process(ents)
+    }
+
+    // Entries of each file. The position of an entry in its file is the object index of its diagnostics.
+    // LDAP attribute names are case-insensitive: names are given the spelling of the mappings, or lowercased
+    fn _read_entries(&self) -> Result<Vec<LdifFile<'_>>> {
+        let mut spellings: HashMap<String, Vec<&str>> = HashMap::new();
+        for attribute in self.ad_user_attributes.mapped_attributes().into_iter().chain(self.ad_group_attributes.mapped_attributes()) {
+            let attribute = LdifConnector::_top_attribute(attribute);
+            let names = spellings.entry(attribute.to_lowercase()).or_default();
+            if !names.contains(&attribute) {
+                names.push(attribute);
+            }
+        }
+
+        let mut files = Vec::new();
+        for fp in &self.ldif_fps {
+            // ldifde -u writes UTF-16
+            let mut file_content = String::new();
+            open_decoded(fp)?.read_to_string(&mut file_content)
+                .with_context(|| format!("Failed to read file: {}", fp))?;
+            let entries = parse_ldif(&file_content)
+                .with_context(|| format!("Failed to parse LDIF file: {}", fp))?
+                .into_iter()
+                .map(|entry| LdifConnector::_respell(entry, &spellings))
+                .collect();
+            files.push((fp.as_str(), entries));
+        }
+        Ok(files)
+    }
+    fn _respell(entry: HashMap<String, Value>, spellings: &HashMap<String, Vec<&str>>) -> HashMap<String, Value> {
+        let mut respelled = HashMap::with_capacity(entry.len());
+        for (name, value) in entry {
+            match spellings.get(&name).map(Vec::as_slice) {
+                Some([spelling]) => { respelled.insert(spelling.to_string(), value); }
+                // User and group mappings spelling the attribute differently
+                Some(names) if !names.is_empty() => {
+                    for spelling in names {
+                        respelled.insert(spelling.to_string(), value.clone());
+                    }
+                }
+                _ => { respelled.insert(name, value); }
+            }
+        }
+        respelled
+    }
+    // Attribute of a mapping path ("manager.cn" -> "manager", "proxyAddresses[0]" -> "proxyAddresses")
+    fn _top_attribute(path: &str) -> &str {
+        path.split(['.', '[']).next().unwrap_or(path)
+    }
+    fn _object_classes(entry: &HashMap<String, Value>) -> Vec<String> {
+        entry.iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case("objectClass"))
+            .map(|(_, v)| match v {
+                Value::String(s) => vec![s.to_lowercase()],
+                Value::Array(values) => values.iter().filter_map(Value::as_str).map(|s| s.to_lowercase()).collect(),
+                _ => Vec::new(),
+            })
+            .unwrap_or_default()
+    }
+    fn _is_group(entry: &HashMap<String, Value>) -> bool {
+        LdifConnector::_object_classes(entry).iter()
+            .any(|c| c == "group" || c == "groupofnames" || c == "groupofuniquenames" || c == "posixgroup")
+    }
+    // Computers (and managed service accounts) are also of class "user" in AD, contacts of class "person"
+    fn _is_user(entry: &HashMap<String, Value>) -> bool {
+        let classes = LdifConnector::_object_classes(entry);
+        !LdifConnector::_is_group(entry) &&
+        !classes.iter().any(|c| c == "computer" || c == "contact") &&
+        classes.iter().any(|c| c == "user" || c == "person" || c == "inetorgperson" || c == "posixaccount")
+    }
+}
+impl TargetSystemConnector for LdifConnector {
+    fn load_accounts(&self) -> Result<Vec<AccountDTO>> {
+        self.load_ldif_users()
+    }
+    fn load_entitlements(&self) -> Result<Vec<EntitlementDTO>> {
+        self.load_ldif_groups()
+    }
+    fn load_accounts_with_diagnostics(&self, diagnostics: &mut Vec<LoadDiagnostic>) -> Result<Vec<AccountDTO>> {
+        Ok(self._load_ldif_users(&self._read_entries()?, diagnostics))
+    }
+    fn load_entitlements_with_diagnostics(&self, diagnostics: &mut Vec<LoadDiagnostic>) -> Result<Vec<EntitlementDTO>> {
+        Ok(self._load_ldif_groups(&self._read_entries()?, diagnostics))
+    }
+    // Users and groups are often in the same files, they are parsed once
+    fn load_all_with_diagnostics(&self, diagnostics: &mut Vec<LoadDiagnostic>) -> Result<(Vec<AccountDTO>, Vec<EntitlementDTO>)> {
+        let files = self._read_entries()?;
+        Ok((self._load_ldif_users(&files, diagnostics), self._load_ldif_groups(&files, diagnostics)))
+    }
+}
+
+// Parses LDIF content (RFC 2849) into one map per entry, in the same shape as the JSON exports:
+// attributes appearing once are strings, attributes appearing several times are arrays.
+// Attribute names are lowercased (LDAP names are case-insensitive), the DN of the entry is stored under the "dn" key
+pub fn parse_ldif(content: &str) -> Result<Vec<HashMap<String, Value>>> {
+    let mut entries = Vec::new();
+    let mut current: HashMap<String, Value> = HashMap::new();
+
+    for (line_no, line) in unfold_lines(content) {
+        if line.is_empty() {
+            // Blank line ends the entry
+            if !current.is_empty() {
+                entries.push(std::mem::take(&mut current));
+            }
+            continue;
+        }
+        if line.starts_with('#') {
+            continue;
+        }
+
+        let (name, value) = parse_line(&line)
+            .ok_or_else(|| anyhow!("Malformed LDIF line {}: {}", line_no, line))?;
+
+        // "version" only appears before the first entry
+        if current.is_empty() && name.eq_ignore_ascii_case("version") {
+            continue;
+        }
+        let value = match value {
+            Some(v) => v,
+            None => continue,
+        };
+
+        match current.get_mut(&name) {
+            None => { current.insert(name, Value::String(value)); }
+            Some(Value::Array(values)) => values.push(Value::String(value)),
+            Some(existing) => {
+                // Second value of the attribute: turns it into a multi-valued attribute
+                let first = existing.take();
+                *existing = Value::Array(vec![first, Value::String(value)]);
+            }
+        }
+    }
+    if !current.is_empty() {
+        entries.push(current);
+    }
+    Ok(entries)
+}
+
+// Joins folded lines (continuation lines start with a single space). Comments can also be folded.
+// Returns the logical lines along with the number of the physical line where they start
+fn unfold_lines(content: &str) -> Vec<(usize, String)> {
+    let mut lines: Vec<(usize, String)> = Vec::new();
+    for (i, raw) in content.lines().enumerate() {
+        let raw = raw.strip_suffix('\r').unwrap_or(raw);
+        match (raw.strip_prefix(' '), lines.last_mut()) {
+            (Some(continuation), Some((_, previous))) if !previous.is_empty() => previous.push_str(continuation),
+            _ => lines.push((i + 1, raw.to_string())),
+        }
+    }
+    lines
+}
+
+// Splits "name: value", "name:: base64" and "name:< url" lines.
+// Attribute options ("member;range=0-1499", "userCertificate;binary") are dropped from the name.
+// URL references are not followed, their value is None
+fn parse_line(line: &str) -> Option<(String, Option<String>)> {
+    let (name, rest) = line.split_once(':')?;
+    let name = name.split(';').next().unwrap_or(name).trim();
+    if name.is_empty() {
+        return None;
+    }
+
+    let value = if let Some(encoded) = rest.strip_prefix(':') {
+        let bytes = STANDARD.decode(encoded.trim()).ok()?;
+        Some(decode_binary_value(name, bytes))
+    } else if rest.starts_with('<') {
+        None
+    } else {
+        // Any number of spaces (FILL) can separate the colon from the value
+        Some(rest.trim_start_matches(' ').to_string())
+    };
+    Some((name.to_lowercase(), value))
+}
+
+// Base64 values are either UTF-8 text with special characters, or binary attributes.
+// Well-known binary attributes are converted to their usual string form, other binary values are kept in base64
+fn decode_binary_value(name: &str, bytes: Vec<u8>) -> String {
+    if name.eq_ignore_ascii_case("objectGUID") {
+        if let Some(guid) = guid_to_string(&bytes) {
+            return guid;
+        }
+    }
+    if name.eq_ignore_ascii_case("objectSid") || name.eq_ignore_ascii_case("sIDHistory") {
+        if let Some(sid) = sid_to_string(&bytes) {
+            return sid;
+        }
+    }
+    match String::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(e) => STANDARD.encode(e.into_bytes()),
+    }
+}
+
+// objectGUID as stored by AD (first three fields little-endian)
+pub fn guid_to_string(b: &[u8]) -> Option<String> {
+    if b.len() != 16 {
+        return None;
+    }
+    Some(format!("{:02x}{:02x}{:02x}{:02x}-{:02x}{:02x}-{:02x}{:02x}-{:02x}{:02x}-{:02x}{:02x}{:02x}{:02x}{:02x}{:02x}",
+        b[3], b[2], b[1], b[0], b[5], b[4], b[7], b[6], b[8], b[9], b[10], b[11], b[12], b[13], b[14], b[15]))
+}
+
+// Binary SID to its S-1-... form
+pub fn sid_to_string(b: &[u8]) -> Option<String> {
+    if b.len() < 8 {
+        return None;
+    }
+    let sub_authority_count = b[1] as usize;
+    if b.len() != 8 + 4 * sub_authority_count {
+        return None;
+    }
+    let authority = b[2..8].iter().fold(0u64, |acc, byte| (acc << 8) | *byte as u64);
+    let mut sid = format!("S-{}-{}", b[0], authority);
+    for chunk in b[8..].chunks(4) {
+        let sub_authority = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        sid.push_str(&format!("-{}", sub_authority));
+    }
+    Some(sid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn folded_lines() {
+        let content = "dn: CN=Doe\\, John,OU=Us\r\n ers,DC=corp\r\n# folded\n  comment\ncn: Doe\n\n ignored\n";
+        assert_eq!(unfold_lines(content), vec![
+            (1, "dn: CN=Doe\\, John,OU=Users,DC=corp".to_string()),
+            (3, "# folded comment".to_string()),
+            (5, "cn: Doe".to_string()),
+            (6, String::new()),
+            // A continuation after a blank line has nothing to continue
+            (7, " ignored".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn entries_and_base64_values() {
+        let content = "version: 1\n\n\
+            dn: CN=Doe,DC=corp\n\
+            objectClass: top\n\
+            objectClass: user\n\
+            description:: TcO8bGxlciwgSsO2cmc=\n\
+            member;range=0-1499: CN=a\n\
+            jpegPhoto:< file:///tmp/photo.jpg\n\
+            sAMAccountName:    jdoe\n\n\
+            dn: CN=Group,DC=corp\n\
+            objectGUID:: AAECAwQFBgcICQoLDA0ODw==\n";
+        let entries = parse_ldif(content).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["objectclass"], serde_json::json!(["top", "user"]));
+        assert_eq!(entries[0]["description"], "Müller, Jörg");
+        assert_eq!(entries[0]["member"], "CN=a");
+        assert!(!entries[0].contains_key("jpegphoto"));
+        assert_eq!(entries[0]["samaccountname"], "jdoe");
+        assert_eq!(entries[1]["objectguid"], "03020100-0504-0706-0809-0a0b0c0d0e0f");
+        assert!(parse_ldif("dn: CN=x\nnot a line\n").is_err());
+    }
+
+    #[test]
+    fn binary_values() {
+        let sid = STANDARD.decode("AQUAAAAAAAUVAAAAAQAAAAIAAAADAAAAUAQAAA==").unwrap();
+        assert_eq!(sid_to_string(&sid).as_deref(), Some("S-1-5-21-1-2-3-1104"));
+        assert_eq!(sid_to_string(&sid[..20]), None);
+        let guid: Vec<u8> = (0..16).collect();
+        assert_eq!(guid_to_string(&guid).as_deref(), Some("03020100-0504-0706-0809-0a0b0c0d0e0f"));
+        assert_eq!(guid_to_string(&guid[..15]), None);
+
+        assert_eq!(decode_binary_value("objectSid", sid), "S-1-5-21-1-2-3-1104");
+        assert_eq!(decode_binary_value("sIDHistory", STANDARD.decode("AQUAAAAAAAUVAAAAAQAAAAIAAAADAAAAUAQAAA==").unwrap()), "S-1-5-21-1-2-3-1104");
+        // Text, and binary attributes without a known form kept in base64
+        assert_eq!(decode_binary_value("cn", "Jörg".as_bytes().to_vec()), "Jörg");
+        assert_eq!(decode_binary_value("thumbnailPhoto", vec![0xff, 0xfe, 0x00]), "//4A");
+        // A GUID of the wrong length is not converted
+        assert_eq!(decode_binary_value("objectGUID", b"short".to_vec()), "short");
+    }
+}
@@ -0,0 +1,636 @@
+use std::{fs, collections::{HashMap, HashSet}};
+use chrono::{DateTime, Days, FixedOffset, NaiveDate, Utc};
+use anyhow::{Result, Context};
+
+use super::dtos::{AccountDTO, AttributeValue, EntitlementDTO};
+use super::traits::TargetSystemConnector;
+use super::diagnostics::{LoadDiagnostic, SourcePosition};
+use super::nesting;
+use super::timestamps::utc;
+
+// Files copied from one Linux host
+#[derive(Debug, Clone)]
+pub struct LinuxHostFiles {
+    pub host: String,
+    pub passwd_fp: String,
+    // Optional, gives lock, password and expiry state
+    pub shadow_fp: Option<String>,
+    pub group_fp: String,
+    // /etc/sudoers and the files in /etc/sudoers.d (include directives are not followed)
+    pub sudoers_fps: Vec<String>,
+}
+
+// Local accounts, groups and sudo rules of one host, or of all the hosts of a host group.
+// Accounts and groups are identified by name; in a host group, the same name on several hosts is one object.
+// Every sudo privilege (hosts, run-as and commands) is an entitlement, users get it directly
+// and %groups get it through nesting
+#[derive(Debug, Clone)]
+pub struct LinuxConnector {
+    pub hosts: Vec<LinuxHostFiles>,
+}
+impl LinuxConnector {
+    pub fn load_linux_accounts(&self) -> Result<Vec<AccountDTO>> {
+        Ok(self._load_linux_accounts(&self._read_hosts()?, &mut Vec::new()))
+    }
+    // Files of every host, read once per load
+    fn _read_hosts(&self) -> Result<Vec<HostData<'_>>> {
+        self.hosts.iter().map(HostData::read).collect()
+    }
+    fn _load_linux_accounts(&self, hosts: &[HostData], diagnostics: &mut Vec<LoadDiagnostic>) -> Vec<AccountDTO> {
+        let mut accounts: HashMap<String, AccountDTO> = HashMap::new();
+        let mut order = Vec::new();
+
+        for host_data in hosts {
+            diagnostics.extend(host_data.account_diagnostics.iter().cloned());
+            for a in host_data.accounts() {
+                match accounts.get_mut(&a.unique_id) {
+                    Some(existing) => LinuxConnector::_merge_account(existing, a, &host_data.host.host),
+                    None => {
+                        order.push(a.unique_id.clone());
+                        accounts.insert(a.unique_id.clone(), a);
+                    }
+                }
+            }
+        }
+        order.into_iter().filter_map(|uid| accounts.remove(&uid)).collect()
+    }
+    // Same account on another host of the host group: access granted on any host counts
+    fn _merge_account(existing: &mut AccountDTO, other: AccountDTO, host: &str) {
+        existing.enabled = match (existing.enabled, other.enabled) {
+            (Some(a), Some(b)) => Some(a || b),
+            (a, b) => a.or(b),
+        };
+        existing.locked = match (existing.locked, other.locked) {
+            (Some(a), Some(b)) => Some(a && b),
+            (a, b) => a.or(b),
+        };
+        if let (Some(memberof), Some(other_memberof)) = (&mut existing.memberof, other.memberof) {
+            for e in other_memberof {
+                if !memberof.contains(&e) {
+                    memberof.push(e);
+                }
+            }
+        }
+        if let Some(Some(AttributeValue::List(hosts))) = existing.other_attributes.get_mut("hosts") {
+            hosts.push(host.into());
+        }
+    }
+
+    pub fn load_linux_groups(&self) -> Result<Vec<EntitlementDTO>> {
+        Ok(self._load_linux_groups(&self._read_hosts()?, &mut Vec::new()))
+    }
+    fn _load_linux_groups(&self, hosts: &[HostData], diagnostics: &mut Vec<LoadDiagnostic>) -> Vec<EntitlementDTO> {
+        let mut ents: HashMap<String, EntitlementDTO> = HashMap::new();
+        let mut order = Vec::new();
+
+        for host_data in hosts {
+            diagnostics.extend(host_data.entitlement_diagnostics.iter().cloned());
+            for e in host_data.entitlements() {
+                match ents.get_mut(&e.unique_id) {
+                    Some(existing) => {
+                        for (existing_list, other_list) in [(&mut existing.members, e.members), (&mut existing.memberof, e.memberof)] {
+                            if let (Some(list), Some(other)) = (existing_list, other_list) {
+                                list.extend(other.into_iter().filter(|m| !list.contains(m)).collect::<Vec<String>>());
+                            }
+                        }
+                    }
+                    None => {
+                        order.push(e.unique_id.clone());
+                        ents.insert(e.unique_id.clone(), e);
+                    }
+                }
+            }
+        }
+
+        // Post-processing (nested groups crawling: %group in sudo rules)
+        let ents = order.into_iter().filter_map(|uid| ents.remove(&uid)).collect();
+        nesting::calculate_indirect_memberof(ents)
+    }
+}
+impl TargetSystemConnector for LinuxConnector {
+    fn load_accounts(&self) -> Result<Vec<AccountDTO>> {
+        self.load_linux_accounts()
+    }
+    fn load_entitlements(&self) -> Result<Vec<EntitlementDTO>> {
+        self.load_linux_groups()
+    }
+    fn load_accounts_with_diagnostics(&self, diagnostics: &mut Vec<LoadDiagnostic>) -> Result<Vec<AccountDTO>> {
+        Ok(self._load_linux_accounts(&self._read_hosts()?, diagnostics))
+    }
+    fn load_entitlements_with_diagnostics(&self, diagnostics: &mut Vec<LoadDiagnostic>) -> Result<Vec<EntitlementDTO>> {
+        Ok(self._load_linux_groups(&self._read_hosts()?, diagnostics))
+    }
+    // Accounts need the groups and sudoers files too, every host is read once
+    fn load_all_with_diagnostics(&self, diagnostics: &mut Vec<LoadDiagnostic>) -> Result<(Vec<AccountDTO>, Vec<EntitlementDTO>)> {
+        let hosts = self._read_hosts()?;
+        Ok((self._load_linux_accounts(&hosts, diagnostics), self._load_linux_groups(&hosts, diagnostics)))
+    }
+}
+
+struct PasswdEntry {
+    line: usize,
+    name: String,
+    uid: String,
+    gid: String,
+    gecos: String,
+    home: String,
+    shell: String,
+}
+struct ShadowEntry {
+    password: String,
+    last_change: Option<DateTime<FixedOffset>>,
+    expire: Option<DateTime<FixedOffset>>,
+}
+struct GroupEntry {
+    line: usize,
+    name: String,
+    gid: String,
+    members: Vec<String>,
+}
+struct SudoRule {
+    unique_id: String,
+    source: String,
+    users: Vec<String>,
+    groups: Vec<String>,
+}
+
+// Everything read from the files of one host
+struct HostData<'a> {
+    host: &'a LinuxHostFiles,
+    passwd: Vec<PasswdEntry>,
+    shadow: HashMap<String, ShadowEntry>,
+    groups: Vec<GroupEntry>,
+    sudo_rules: Vec<SudoRule>,
+    // Problems of passwd and shadow, and of group and sudoers. Each load reports its own
+    account_diagnostics: Vec<LoadDiagnostic>,
+    entitlement_diagnostics: Vec<LoadDiagnostic>,
+}
+impl<'a> HostData<'a> {
+    fn read(host: &'a LinuxHostFiles) -> Result<Self> {
+        let mut account_diagnostics = Vec::new();
+        let mut entitlement_diagnostics = Vec::new();
+
+        let passwd: Vec<PasswdEntry> = read_colon_file(&host.passwd_fp)?
+            .into_iter()
+            .filter(|(i, f)| check_fields(&host.passwd_fp, *i, f, 7, &mut account_diagnostics))
+            .map(|(i, f)| PasswdEntry { line: i, name: f[0].clone(), uid: f[2].clone(), gid: f[3].clone(), gecos: f[4].clone(), home: f[5].clone(), shell: f[6].clone() })
+            .collect();
+
+        let mut shadow = HashMap::new();
+        if let Some(shadow_fp) = &host.shadow_fp {
+            for (i, f) in read_colon_file(shadow_fp)? {
+                if !check_fields(shadow_fp, i, &f, 8, &mut account_diagnostics) {
+                    continue;
+                }
+                for (field, attribute) in [(2, "last_change"), (7, "expire")] {
+                    let problem = match f[field].trim().parse::<i64>() {
+                        _ if f[field].trim().is_empty() => None,
+                        Err(_) => Some("is not a number of days"),
+                        Ok(days) if days > 0 && days_to_date(&f[field]).is_none() => Some("days is out of the date range"),
+                        Ok(_) => None,
+                    };
+                    if let Some(problem) = problem {
+                        account_diagnostics.push(LoadDiagnostic::warning(shadow_fp, Some(i), Some(attribute),
+                            &format!("'{}' {}, ignored", f[field], problem)));
+                    }
+                }
+                shadow.insert(f[0].clone(), ShadowEntry { password: f[1].clone(), last_change: days_to_date(&f[2]), expire: days_to_date(&f[7]) });
+            }
+        }
+
+        let groups = read_colon_file(&host.group_fp)?
+            .into_iter()
+            .filter(|(i, f)| check_fields(&host.group_fp, *i, f, 4, &mut entitlement_diagnostics))
+            .map(|(i, f)| GroupEntry {
+                line: i,
+                name: f[0].clone(),
+                gid: f[2].clone(),
+                members: f[3].split(',').map(|m| m.trim()).filter(|m| !m.is_empty()).map(|m| m.to_string()).collect(),
+            })
+            .collect();
+
+        let mut host_data = HostData { host, passwd, shadow, groups, sudo_rules: Vec::new(), account_diagnostics, entitlement_diagnostics: Vec::new() };
+        for fp in &host.sudoers_fps {
+            let content = fs::read_to_string(fp)
+                .with_context(|| format!("Failed to read file: {}", fp))?;
+            host_data.sudo_rules.extend(host_data._parse_sudoers(&content, fp, &mut entitlement_diagnostics));
+        }
+        host_data.entitlement_diagnostics = entitlement_diagnostics;
+        Ok(host_data)
+    }
+
+    fn accounts(&self) -> Vec<AccountDTO> {
+        let now = Utc::now();
+        let group_by_gid: HashMap<&str, &str> = self.groups.iter().map(|g| (g.gid.as_str(), g.name.as_str())).collect();
+
+        self.passwd.iter().map(|p| {
+            let shadow = self.shadow.get(&p.name);
+
+            // Primary group, supplementary groups and sudo rules granted to the user
+            let mut memberof: Vec<String> = group_by_gid.get(p.gid.as_str()).map(|g| vec![g.to_string()]).unwrap_or_default();
+            for g in self.groups.iter().filter(|g| g.members.contains(&p.name)) {
+                if !memberof.contains(&g.name) {
+                    memberof.push(g.name.clone());
+                }
+            }
+            for rule in self.sudo_rules.iter().filter(|r| r.users.contains(&p.name)) {
+                if !memberof.contains(&rule.unique_id) {
+                    memberof.push(rule.unique_id.clone());
+                }
+            }
+
+            let interactive = !(p.shell.ends_with("nologin") || p.shell.ends_with("/false") || p.shell.is_empty());
+            let expired = shadow.and_then(|s| s.expire).map(|d| d <= now).unwrap_or(false);
+            let password_state = shadow.map(|s| {
+                if s.password == "*" || s.password == "!!" { "not set" }
+                else if s.password.starts_with('!') { "locked" }
+                else if s.password.is_empty() { "empty" }
+                else { "set" }
+            });
+
+            let mut other_attributes = HashMap::new();
+            other_attributes.insert("uid".to_string(), Some(id_value(&p.uid)));
+            other_attributes.insert("gid".to_string(), Some(id_value(&p.gid)));
+            other_attributes.insert("home".to_string(), Some(p.home.clone().into()));
+            other_attributes.insert("shell".to_string(), Some(p.shell.clone().into()));
+            other_attributes.insert("password_state".to_string(), password_state.map(AttributeValue::from));
+            other_attributes.insert("hosts".to_string(), Some(AttributeValue::List(vec![self.host.host.clone().into()])));
+
+            AccountDTO {
+                unique_id: p.name.clone(),
+                alternate_ids: Vec::new(),
+                sids: Vec::new(),
+                display_name: None,
+                // Full name is the first field of GECOS
+                description: p.gecos.split(',').next().filter(|s| !s.is_empty()).map(|s| s.to_string()),
+                created: None,
+                last_logon: None,
+                password_last_set: shadow.and_then(|s| s.last_change),
+                expiration_date: shadow.and_then(|s| s.expire),
+                enabled: Some(interactive && !expired),
+                deleted: None,
+                locked: shadow.map(|s| s.password.starts_with('!')),
+                flags: None,
+                memberof: Some(memberof),
+                ou: None,
+                manager: None,
+                other_attributes,
+                position: Some(SourcePosition::new(&self.host.passwd_fp, p.line)),
+            }
+        }).collect()
+    }
+
+    fn entitlements(&self) -> Vec<EntitlementDTO> {
+        let mut ents = Vec::new();
+
+        for g in &self.groups {
+            // Users having the group as primary group are members too
+            let mut members = g.members.clone();
+            for p in self.passwd.iter().filter(|p| p.gid == g.gid) {
+                if !members.contains(&p.name) {
+                    members.push(p.name.clone());
+                }
+            }
+            let memberof: Vec<String> = self.sudo_rules.iter()
+                .filter(|r| r.groups.contains(&g.name))
+                .map(|r| r.unique_id.clone())
+                .collect();
+
+            let mut other_attributes = HashMap::new();
+            other_attributes.insert("gid".to_string(), Some(id_value(&g.gid)));
+            other_attributes.insert("type".to_string(), Some("group".into()));
+
+            ents.push(EntitlementDTO {
+                unique_id: g.name.clone(),
+                alternate_ids: Vec::new(),
+                sids: Vec::new(),
+                display_name: None,
+                description: None,
+                created: None,
+                memberof: Some(memberof),
+                all_indirect_memberof: Vec::new(),
+                members: Some(members),
+                member_groups: Some(Vec::new()),
+                ou: None,
+                other_attributes,
+                ts_owners: None,
+                position: Some(SourcePosition::new(&self.host.group_fp, g.line)),
+            });
+        }
+
+        for rule in &self.sudo_rules {
+            let mut other_attributes = HashMap::new();
+            other_attributes.insert("type".to_string(), Some("sudo".into()));
+
+            ents.push(EntitlementDTO {
+                unique_id: rule.unique_id.clone(),
+                alternate_ids: Vec::new(),
+                sids: Vec::new(),
+                display_name: None,
+                description: Some(rule.source.clone()),
+                created: None,
+                memberof: Some(Vec::new()),
+                all_indirect_memberof: Vec::new(),
+                members: Some(rule.users.iter().cloned().chain(rule.groups.iter().cloned()).collect()),
+                member_groups: Some(rule.groups.clone()),
+                ou: None,
+                other_attributes,
+                ts_owners: None,
+                position: None,
+            });
+        }
+        ents
+    }
+
+    // Parses user specifications ("user_list host_list = (runas) commands").
+    // User_Alias, %group, #uid, ALL and negations are expanded; other aliases are kept as written
+    fn _parse_sudoers(&self, content: &str, fp: &str, diagnostics: &mut Vec<LoadDiagnostic>) -> Vec<SudoRule> {
+        let mut user_aliases: HashMap<String, Vec<String>> = HashMap::new();
+        let mut rules: Vec<SudoRule> = Vec::new();
+
+        for (line_no, line) in join_continuations(content) {
+            // Include directives are skipped, files are given explicitly
+            let line = line.trim();
+            if line.starts_with("#include") || line.starts_with("@include") {
+                continue;
+            }
+            let line = strip_comment(line);
+            if line.is_empty() || line.starts_with("Defaults") {
+                continue;
+            }
+            if let Some(definitions) = line.strip_prefix("User_Alias") {
+                for definition in definitions.split(':') {
+                    if let Some((name, list)) = definition.split_once('=') {
+                        let users = self._expand_users(list, &user_aliases);
+                        user_aliases.insert(name.trim().to_string(), users);
+                    }
+                }
+                continue;
+            }
+            if ["Runas_Alias", "Host_Alias", "Cmnd_Alias", "Cmd_Alias"].iter().any(|a| line.starts_with(a)) {
+                continue;
+            }
+
+            let (left, privileges) = match line.split_once('=') {
+                Some(parts) => parts,
+                None => {
+                    diagnostics.push(LoadDiagnostic::warning(fp, Some(line_no - 1), None,
+                        &format!("Line {} is not a user specification, ignored", line_no)));
+                    continue;
+                }
+            };
+            let (user_list, host_list) = split_user_and_hosts(left);
+
+            // "alice web1 = /bin/a : db1 = /bin/b" grants one privilege per host list
+            let mut specs = split_host_specs(privileges).into_iter();
+            let mut host_privileges = vec![(host_list, specs.next().unwrap_or_default())];
+            for spec in specs {
+                match spec.split_once('=') {
+                    Some((hosts, privilege)) => host_privileges.push((hosts.split_whitespace().collect(), privilege)),
+                    None => diagnostics.push(LoadDiagnostic::warning(fp, Some(line_no - 1), None,
+                        &format!("Line {} has a host specification without '=' ({}), ignored", line_no, spec.trim()))),
+                }
+            }
+
+            let principals = self._expand_users(&user_list, &user_aliases);
+            let (groups, users): (Vec<String>, Vec<String>) = principals.into_iter().partition(|p| p.starts_with('%'));
+            let groups: Vec<String> = groups.into_iter().map(|g| g.trim_start_matches('%').to_string()).collect();
+
+            for (host_list, privilege) in host_privileges {
+                let privilege = privilege.split_whitespace().collect::<Vec<&str>>().join(" ");
+                let unique_id = format!("sudo: {} = {}", host_list, privilege);
+
+                // The same privilege granted on several lines is a single entitlement
+                match rules.iter_mut().find(|r| r.unique_id == unique_id) {
+                    Some(rule) => {
+                        rule.users.extend(users.iter().filter(|u| !rule.users.contains(u)).cloned().collect::<Vec<String>>());
+                        rule.groups.extend(groups.iter().filter(|g| !rule.groups.contains(g)).cloned().collect::<Vec<String>>());
+                    }
+                    None => rules.push(SudoRule { unique_id, source: format!("{}:{}", fp, line_no), users: users.clone(), groups: groups.clone() }),
+                }
+            }
+        }
+        rules
+    }
+    // Expands a sudoers user list. Groups are returned with their '%' prefix
+    fn _expand_users(&self, list: &str, user_aliases: &HashMap<String, Vec<String>>) -> Vec<String> {
+        let mut included: Vec<String> = Vec::new();
+        let mut excluded: HashSet<String> = HashSet::new();
+
+        for item in list.split(',').map(|i| i.trim()).filter(|i| !i.is_empty()) {
+            let (negated, item) = match item.strip_prefix('!') {
+                Some(rest) => (true, rest.trim()),
+                None => (false, item),
+            };
+            let expanded: Vec<String> = if item == "ALL" {
+                self.passwd.iter().map(|p| p.name.clone()).collect()
+            } else if let Some(uid) = item.strip_prefix('#') {
+                self.passwd.iter().filter(|p| p.uid == uid).map(|p| p.name.clone()).collect()
+            } else if let Some(users) = user_aliases.get(item) {
+                users.clone()
+            } else if item.starts_with("%:") || item.starts_with('+') {
+                // Non-Unix groups and netgroups can't be resolved from the local files
+                Vec::new()
+            } else {
+                vec![item.to_string()]
+            };
+            if negated {
+                excluded.extend(expanded);
+            } else {
+                included.extend(expanded.into_iter().filter(|u| !included.contains(u)).collect::<Vec<String>>());
+            }
+        }
+        included.retain(|u| !excluded.contains(u));
+        included
+    }
+}
+
+// "alice, bob  ALL, !web1" -> ("alice,bob", "ALL,!web1"). Lists can have spaces after the commas
+fn split_user_and_hosts(left: &str) -> (String, String) {
+    let mut lists: Vec<String> = Vec::new();
+    for token in left.split_whitespace() {
+        match lists.last_mut() {
+            Some(last) if last.ends_with(',') || token.starts_with(',') => last.push_str(token),
+            _ => lists.push(token.to_string()),
+        }
+    }
+    let mut lists = lists.into_iter();
+    let users = lists.next().unwrap_or_default();
+    let hosts = lists.collect::<Vec<String>>().join(" ");
+    (users, hosts)
+}
+
+// Splits the privileges of a user specification on the ':' separating host lists:
+// "/bin/a : db1 = /bin/b" -> ["/bin/a ", " db1 = /bin/b"]. Escaped colons, colons of Runas lists "(root:wheel)"
+// and of tags "NOPASSWD:" are not separators
+fn split_host_specs(privileges: &str) -> Vec<&str> {
+    let mut specs = Vec::new();
+    let mut start = 0;
+    let mut depth = 0;
+    let mut escaped = false;
+    for (i, c) in privileges.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ':' if depth == 0 => {
+                let word = privileges[start..i].rsplit(|c: char| c.is_whitespace() || c == ')' || c == ':').next().unwrap_or_default();
+                if !SUDO_TAGS.contains(&word) {
+                    specs.push(&privileges[start..i]);
+                    start = i + 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    specs.push(&privileges[start..]);
+    specs
+}
+const SUDO_TAGS: [&str; 16] = ["PASSWD", "NOPASSWD", "EXEC", "NOEXEC", "SETENV", "NOSETENV", "LOG_INPUT", "NOLOG_INPUT",
+    "LOG_OUTPUT", "NOLOG_OUTPUT", "MAIL", "NOMAIL", "FOLLOW", "NOFOLLOW", "INTERCEPT", "NOINTERCEPT"];
+
+// '#' starts a comment, except in "#uid" user references
+fn strip_comment(line: &str) -> &str {
+    for (i, _) in line.match_indices('#') {
+        if !line[i + 1..].starts_with(|c: char| c.is_ascii_digit()) {
+            return line[..i].trim();
+        }
+    }
+    line
+}
+
+// Joins lines ending with a backslash. Returns the logical lines with the number of their first physical line
+fn join_continuations(content: &str) -> Vec<(usize, String)> {
+    let mut lines = Vec::new();
+    let mut current: Option<(usize, String)> = None;
+    for (i, raw) in content.lines().enumerate() {
+        let (text, continues) = match raw.strip_suffix('\\') {
+            Some(t) => (t, true),
+            None => (raw, false),
+        };
+        let entry = current.get_or_insert((i + 1, String::new()));
+        entry.1.push_str(text);
+        if !continues {
+            lines.extend(current.take());
+        }
+    }
+    lines.extend(current);
+    lines
+}
+
+// Fields of each line, with the position of the line in the file
+fn read_colon_file(fp: &str) -> Result<Vec<(usize, Vec<String>)>> {
+    let content = fs::read_to_string(fp)
+        .with_context(|| format!("Failed to read file: {}", fp))?;
+    Ok(content.lines()
+        .map(|l| l.trim_end_matches('\r'))
+        .enumerate()
+        .filter(|(_, l)| !l.is_empty() && !l.starts_with('#'))
+        .map(|(i, l)| (i, l.split(':').map(|f| f.to_string()).collect()))
+        .collect())
+}
+// Lines with missing fields are skipped
+fn check_fields(fp: &str, index: usize, fields: &[String], expected: usize, diagnostics: &mut Vec<LoadDiagnostic>) -> bool {
+    if fields.len() < expected {
+        diagnostics.push(LoadDiagnostic::error(fp, Some(index), None,
+            &format!("Line {} has {} fields instead of {}, ignored", index + 1, fields.len(), expected)));
+    }
+    fields.len() >= expected
+}
+
+// Dates in shadow are days since 1970-01-01. Empty means not set, 0 in last change means "change at next logon"
+fn days_to_date(days: &str) -> Option<DateTime<FixedOffset>> {
+    let days = days.trim().parse::<i64>().ok().filter(|d| *d > 0)?;
+    // Corrupt files can hold any number, checked_add_days doesn't panic on overflow
+    let date = NaiveDate::from_ymd_opt(1970, 1, 1)?.checked_add_days(Days::new(days as u64))?;
+    date.and_hms_opt(0, 0, 0).map(utc)
+}
+// uid and gid as numbers, unless the file holds something else
+fn id_value(id: &str) -> AttributeValue {
+    id.trim().parse::<i64>().map(AttributeValue::Integer).unwrap_or(AttributeValue::String(id.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn host_data(host: &LinuxHostFiles) -> HostData<'_> {
+        let passwd = [("alice", "1001"), ("bob", "1002"), ("carol", "1003"), ("dave", "1004")].iter().enumerate()
+            .map(|(line, (name, uid))| PasswdEntry { line, name: name.to_string(), uid: uid.to_string(), gid: "100".to_string(),
+                gecos: String::new(), home: format!("/home/{}", name), shell: "/bin/bash".to_string() })
+            .collect();
+        HostData { host, passwd, shadow: HashMap::new(), groups: Vec::new(), sudo_rules: Vec::new(),
+            account_diagnostics: Vec::new(), entitlement_diagnostics: Vec::new() }
+    }
+
+    #[test]
+    fn sudoers() {
+        let host = LinuxHostFiles { host: "web1".to_string(), passwd_fp: "passwd".to_string(), shadow_fp: None,
+            group_fp: "group".to_string(), sudoers_fps: vec!["sudoers".to_string()] };
+        let content = "Defaults env_reset\n\
+            # comment\n\
+            User_Alias ADMINS = alice, bob, \\\n    carol\n\
+            ADMINS ALL=(ALL) ALL\n\
+            %wheel, #1003 ALL = (root) NOPASSWD: /bin/systemctl  # restart\n\
+            ALL, !bob web1 = /usr/bin/top\n\
+            dave ALL=(ALL)   ALL\n\
+            garbage line\n\
+            #includedir /etc/sudoers.d\n";
+        let mut diagnostics = Vec::new();
+        let rules = host_data(&host)._parse_sudoers(content, "sudoers", &mut diagnostics);
+
+        let found: Vec<(&str, &str, Vec<&str>, Vec<&str>)> = rules.iter()
+            .map(|r| (r.unique_id.as_str(), r.source.as_str(), r.users.iter().map(|u| u.as_str()).collect(), r.groups.iter().map(|g| g.as_str()).collect()))
+            .collect();
+        assert_eq!(found, vec![
+            // Alias over a continuation line, and the same privilege on another line
+            ("sudo: ALL = (ALL) ALL", "sudoers:5", vec!["alice", "bob", "carol", "dave"], vec![]),
+            // Groups, #uid and a trailing comment
+            ("sudo: ALL = (root) NOPASSWD: /bin/systemctl", "sudoers:6", vec!["carol"], vec!["wheel"]),
+            // ALL and a negation
+            ("sudo: web1 = /usr/bin/top", "sudoers:7", vec!["alice", "carol", "dave"], vec![]),
+        ]);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].object_index, Some(8));
+    }
+
+    #[test]
+    fn sudoers_host_specifications() {
+        let host = LinuxHostFiles { host: "web1".to_string(), passwd_fp: "passwd".to_string(), shadow_fp: None,
+            group_fp: "group".to_string(), sudoers_fps: vec!["sudoers".to_string()] };
+        let content = "alice web1 = /bin/a : db1, db2 = NOPASSWD: /bin/b\n\
+            %admins ALL = (root:wheel) SETENV:NOPASSWD: /bin/c\\:d\n\
+            bob ALL = /bin/e : /bin/f\n";
+        let mut diagnostics = Vec::new();
+        let rules = host_data(&host)._parse_sudoers(content, "sudoers", &mut diagnostics);
+
+        let found: Vec<(&str, &str, Vec<&str>, Vec<&str>)> = rules.iter()
+            .map(|r| (r.unique_id.as_str(), r.source.as_str(), r.users.iter().map(|u| u.as_str()).collect(), r.groups.iter().map(|g| g.as_str()).collect()))
+            .collect();
+        assert_eq!(found, vec![
+            ("sudo: web1 = /bin/a", "sudoers:1", vec!["alice"], vec![]),
+            ("sudo: db1,db2 = NOPASSWD: /bin/b", "sudoers:1", vec!["alice"], vec![]),
+            // Colons of Runas lists, tags and escaped ones don't separate host lists
+            ("sudo: ALL = (root:wheel) SETENV:NOPASSWD: /bin/c\\:d", "sudoers:2", vec![], vec!["admins"]),
+            ("sudo: ALL = /bin/e", "sudoers:3", vec!["bob"], vec![]),
+        ]);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].object_index, Some(2));
+    }
+
+    #[test]
+    fn user_and_host_lists() {
+        assert_eq!(split_user_and_hosts("alice, bob  ALL, !web1"), ("alice,bob".to_string(), "ALL,!web1".to_string()));
+        assert_eq!(split_user_and_hosts("%admins ALL"), ("%admins".to_string(), "ALL".to_string()));
+        assert_eq!(strip_comment("#1001 ALL # note"), "#1001 ALL");
+    }
+
+    #[test]
+    fn shadow_days() {
+        assert_eq!(days_to_date("19448").map(|d| d.to_rfc3339()).as_deref(), Some("2023-04-01T00:00:00+00:00"));
+        assert_eq!(days_to_date("0"), None);
+        assert_eq!(days_to_date(""), None);
+        assert_eq!(days_to_date("99999999999999"), None);
+    }
+}
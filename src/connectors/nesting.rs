@@ -0,0 +1,106 @@
+use std::collections::{HashMap, HashSet};
+
+use super::dtos::EntitlementDTO;
+
+// Group nesting post-processing shared by the connectors.
+// Membership values are expected to be unique_ids of the groups read.
+
+pub fn calculate_indirect_memberof(ents: Vec<EntitlementDTO>) -> Vec<EntitlementDTO> {
+    
+    // Consumes and transforms ents into a lookup structure
+    let mut ent_lookup = EntitlementLookup::from(ents);
+    
+    // HashMap of ent_uid and all indirect parents found
+    let mut indirect_parents_found: HashMap<String, Vec<String>> = HashMap::new(); 
+
+    // For each EntitlementDTO, it crawls for indirect memberships
+    for ent in ent_lookup.0.values() {
+
+        // Set structure to stop recurssion by not crawling on parents already found
+        let mut set: HashSet<&String> = HashSet::new();
+        if let Some(parents) = &ent.memberof {
+            let mut ps: Vec<&String> = parents.iter().collect(); // &Vec<String> to Vec<&String>
+            while !ps.is_empty() {
+                let mut new_insertions = Vec::new();
+                for p in ps {
+                    let p_ref = p;
+                    if set.insert(p){
+                        new_insertions.push(p_ref);
+                    }
+                }
+                ps = ent_lookup.get_parents_of_all_groups(new_insertions); 
+            }
+        } 
+        // Storing result of crawling for specific EntitlementDTO. Clones only necessary data to be stored
+        indirect_parents_found.insert(ent.unique_id.clone(), set.into_iter().cloned().collect());
+    }
+
+    // Move found data to EntitlementsDTO
+    for ent in ent_lookup.0.values_mut() {
+        if let Some(all_indirect_parents) = indirect_parents_found.remove(&ent.unique_id){
+            ent.all_indirect_memberof = all_indirect_parents; 
+        }
+    }
+    let ret_val: Vec<EntitlementDTO> = ent_lookup.0.drain().map(|(_,v)| v).collect();
+    ret_val
+}
+pub fn calculate_memberof(ents: &mut Vec<EntitlementDTO>) {
+    let mut group_memberofs = HashMap::new(); 
+        for e in ents.iter() {
+            if let Some(mem_groups) = &e.member_groups {
+                for mem_group in mem_groups {
+                    // Creates HashMap of all memberof found so far. Clone is fine, this is new data being created
+                    let entry = group_memberofs.entry(mem_group.clone()).or_insert(HashSet::from([e.unique_id.clone()]));
+                    entry.insert(e.unique_id.clone());
+                }
+            }
+        }
+        for e in ents {
+            let memberof_hm = group_memberofs.remove(&e.unique_id);
+            //Converts HashSet to Vec
+            e.memberof = memberof_hm.map(|hs| hs.into_iter().collect());
+        }
+}
+
+// Adds the memberof implied by member_groups, keeping the memberof already known
+pub fn complete_memberof(ents: &mut [EntitlementDTO]) {
+    let mut parents: HashMap<String, Vec<String>> = HashMap::new();
+    for e in ents.iter() {
+        for mem_group in e.member_groups.iter().flatten() {
+            parents.entry(mem_group.clone()).or_default().push(e.unique_id.clone());
+        }
+    }
+    for e in ents.iter_mut() {
+        if let Some(new_parents) = parents.remove(&e.unique_id) {
+            let memberof = e.memberof.get_or_insert_with(Vec::new);
+            for p in new_parents {
+                if !memberof.contains(&p) {
+                    memberof.push(p);
+                }
+            }
+        }
+    }
+}
+
+struct EntitlementLookup(HashMap<String, EntitlementDTO>);
+impl EntitlementLookup {
+    fn from(ents: Vec<EntitlementDTO>) -> Self {
+        let mut el = EntitlementLookup(HashMap::new());
+        for ent in ents {
+            el.0.insert(ent.unique_id.clone(), ent);
+        }
+        el
+    }
+    fn get_parents_of_all_groups(&self, ents_uids: Vec<&String>) -> Vec<&String> {
+        let mut accumulator = Vec::new();
+        for indirect_ents_uid in ents_uids {
+            if let Some(indirect_membership) = self.0.get(indirect_ents_uid) {
+                if let Some(parents) = &indirect_membership.memberof {
+                    accumulator.extend(parents.iter().collect::<Vec<&String>>()); 
+                    //To capture history. Instead of Vec<String>.. Vec<String(original),Vec<String(path)>
+                }
+            }
+        }
+        accumulator
+    }
+}
@@ -0,0 +1,789 @@
+use std::{fs, collections::HashMap};
+use chrono::{DateTime, FixedOffset, Utc};
+use anyhow::{Result, Context};
+
+use super::dtos::{AccountDTO, AttributeValue, EntitlementDTO};
+use super::traits::TargetSystemConnector;
+use super::diagnostics::{LoadDiagnostic, SourcePosition};
+use super::timestamps::parse_iso8601;
+use super::nesting;
+
+const PUBLIC: &str = "PUBLIC";
+// Role attributes reviewed as entitlements
+const ROLE_ATTRIBUTES: [&str; 5] = ["SUPERUSER", "CREATEDB", "CREATEROLE", "REPLICATION", "BYPASSRLS"];
+
+// PostgreSQL cluster, from `pg_dumpall --roles-only` and the GRANT/REVOKE/OWNER TO statements of schema dumps
+// (pg_dump --schema-only, or a full pg_dumpall). Statements are replayed in file order.
+// Login roles are accounts. Non-login roles, roles having members, role attributes (SUPERUSER...)
+// and object privileges ("SELECT ON TABLE public.orders") are entitlements.
+// Role memberships and privileges granted to roles go through the usual nesting logic
+#[derive(Debug, Clone)]
+pub struct PostgresConnector {
+    pub roles_fps: Vec<String>,
+    // The database of the objects is taken from \connect lines, if any
+    pub schema_fps: Vec<String>,
+}
+impl PostgresConnector {
+    pub fn load_pg_accounts(&self) -> Result<Vec<AccountDTO>> {
+        Ok(self._load_pg_accounts(&self._read_cluster()?))
+    }
+    fn _load_pg_accounts(&self, cluster: &Cluster) -> Vec<AccountDTO> {
+        let now = Utc::now();
+
+        let accounts = cluster.roles.iter()
+            .filter(|r| r.login)
+            .map(|r| {
+                let mut other_attributes = HashMap::new();
+                other_attributes.insert("inherit".to_string(), Some(r.inherit.into()));
+                other_attributes.insert("password_set".to_string(), Some(r.password.into()));
+                other_attributes.insert("connection_limit".to_string(), r.connection_limit.as_ref()
+                    .map(|l| l.parse::<i64>().map(AttributeValue::Integer).unwrap_or(AttributeValue::String(l.clone()))));
+
+                AccountDTO {
+                    unique_id: r.name.clone(),
+                    alternate_ids: Vec::new(),
+                    sids: Vec::new(),
+                    display_name: None,
+                    description: r.description.clone(),
+                    created: None,
+                    last_logon: None,
+                    password_last_set: None,
+                    expiration_date: r.valid_until,
+                    enabled: Some(r.valid_until.map(|d| d > now).unwrap_or(true)),
+                    deleted: None,
+                    locked: None,
+                    flags: None,
+                    memberof: Some(cluster.memberof(&r.name)),
+                    ou: None,
+                    manager: None,
+                    other_attributes,
+                    position: Some(r.position.clone()),
+                }
+            })
+            .collect();
+        accounts
+    }
+
+    pub fn load_pg_entitlements(&self) -> Result<Vec<EntitlementDTO>> {
+        Ok(self._load_pg_entitlements(&self._read_cluster()?))
+    }
+    fn _load_pg_entitlements(&self, cluster: &Cluster) -> Vec<EntitlementDTO> {
+        let mut ents = Vec::new();
+
+        for r in cluster.roles.iter().filter(|r| cluster.is_group(&r.name)) {
+            let mut other_attributes = HashMap::new();
+            other_attributes.insert("type".to_string(), Some("role".into()));
+            other_attributes.insert("login".to_string(), Some(r.login.into()));
+            other_attributes.insert("inherit".to_string(), Some(r.inherit.into()));
+            let mut e = cluster.entitlement(&r.name, r.description.clone(), other_attributes);
+            e.position = Some(r.position.clone());
+            ents.push(e);
+        }
+        for attribute in ROLE_ATTRIBUTES.iter().filter(|a| cluster.roles.iter().any(|r| r.attributes.iter().any(|ra| ra == *a))) {
+            let other_attributes = HashMap::from([("type".to_string(), Some("role attribute".into()))]);
+            ents.push(cluster.entitlement(attribute, None, other_attributes));
+        }
+        if cluster.grants.iter().any(|(_, grantee)| grantee == PUBLIC) {
+            let other_attributes = HashMap::from([("type".to_string(), Some("pseudo-role".into()))]);
+            ents.push(cluster.entitlement(PUBLIC, Some("Every role".to_string()), other_attributes));
+        }
+        for (unique_id, p) in &cluster.privileges {
+            if !cluster.grants.iter().any(|(id, _)| id == unique_id) {
+                continue;
+            }
+            let mut other_attributes = HashMap::new();
+            other_attributes.insert("type".to_string(), Some("privilege".into()));
+            other_attributes.insert("privilege".to_string(), Some(p.privilege.clone().into()));
+            other_attributes.insert("object_type".to_string(), Some(p.object_type.clone().into()));
+            other_attributes.insert("object".to_string(), Some(p.object.clone().into()));
+            other_attributes.insert("database".to_string(), p.database.clone().map(AttributeValue::from));
+            ents.push(cluster.entitlement(unique_id, None, other_attributes));
+        }
+
+        // Post-processing (nested groups crawling)
+        nesting::calculate_indirect_memberof(ents)
+    }
+
+    fn _read_cluster(&self) -> Result<Cluster> {
+        let mut cluster = Cluster::default();
+        for fp in self.roles_fps.iter().chain(&self.schema_fps) {
+            let content = fs::read_to_string(fp)
+                .with_context(|| format!("Failed to read file: {}", fp))?;
+            cluster.database = None;
+            cluster.source = fp.clone();
+            for (index, statement) in split_statements(content.trim_start_matches('\u{feff}')).into_iter().enumerate() {
+                cluster.statement_index = index;
+                match statement {
+                    Statement::Connect(database) => cluster.database = Some(database),
+                    Statement::Sql(tokens) => cluster.apply(&tokens),
+                }
+            }
+        }
+        Ok(cluster)
+    }
+}
+impl TargetSystemConnector for PostgresConnector {
+    fn load_accounts(&self) -> Result<Vec<AccountDTO>> {
+        self.load_pg_accounts()
+    }
+    fn load_entitlements(&self) -> Result<Vec<EntitlementDTO>> {
+        self.load_pg_entitlements()
+    }
+    fn load_accounts_with_diagnostics(&self, diagnostics: &mut Vec<LoadDiagnostic>) -> Result<Vec<AccountDTO>> {
+        let cluster = self._read_cluster()?;
+        diagnostics.extend(cluster.diagnostics.iter().cloned());
+        Ok(self._load_pg_accounts(&cluster))
+    }
+    // Both come from the same statements, they are replayed once and their problems reported once
+    fn load_all_with_diagnostics(&self, diagnostics: &mut Vec<LoadDiagnostic>) -> Result<(Vec<AccountDTO>, Vec<EntitlementDTO>)> {
+        let cluster = self._read_cluster()?;
+        diagnostics.extend(cluster.diagnostics.iter().cloned());
+        Ok((self._load_pg_accounts(&cluster), self._load_pg_entitlements(&cluster)))
+    }
+}
+
+struct Role {
+    name: String,
+    login: bool,
+    inherit: bool,
+    password: bool,
+    valid_until: Option<DateTime<FixedOffset>>,
+    connection_limit: Option<String>,
+    attributes: Vec<String>,
+    description: Option<String>,
+    // Statement that created the role, or first altered it
+    position: SourcePosition,
+}
+impl Role {
+    fn new(name: &str, login: bool, position: SourcePosition) -> Self {
+        Role { name: name.to_string(), login, inherit: true, password: false,
+            valid_until: None, connection_limit: None, attributes: Vec::new(), description: None, position }
+    }
+}
+struct Privilege {
+    privilege: String,
+    object_type: String,
+    object: String,
+    database: Option<String>,
+}
+
+// State of the cluster after replaying the statements
+#[derive(Default)]
+struct Cluster {
+    roles: Vec<Role>,
+    // (role, member), in grant order
+    memberships: Vec<(String, String)>,
+    privileges: Vec<(String, Privilege)>,
+    // (privilege unique_id, grantee)
+    grants: Vec<(String, String)>,
+    database: Option<String>,
+    // File and position of the statement being replayed, for the diagnostics
+    source: String,
+    statement_index: usize,
+    diagnostics: Vec<LoadDiagnostic>,
+}
+impl Cluster {
+    fn apply(&mut self, tokens: &[Token]) {
+        let keyword = |i: usize| tokens.get(i).map(Token::keyword).unwrap_or_default();
+
+        match (keyword(0).as_str(), keyword(1).as_str()) {
+            ("CREATE", "ROLE" | "USER" | "GROUP") => {
+                if let Some(name) = tokens.get(2).map(Token::identifier) {
+                    if !self.roles.iter().any(|r| r.name == name) {
+                        // CREATE USER is CREATE ROLE with LOGIN
+                        self.roles.push(Role::new(&name, keyword(1) == "USER", SourcePosition::new(&self.source, self.statement_index)));
+                    }
+                    self._apply_role_options(&name, &tokens[3..]);
+                }
+            }
+            ("ALTER", "ROLE" | "USER" | "GROUP") if keyword(2) != "ALL" => {
+                let name = match tokens.get(2) {
+                    Some(t) => t.identifier(),
+                    None => return,
+                };
+                if keyword(1) == "GROUP" && keyword(3) == "DROP" {
+                    let (members, _) = identifier_list(tokens.get(5..).unwrap_or_default());
+                    self.memberships.retain(|(r, m)| !(r == &name && members.contains(m)));
+                } else {
+                    // ALTER GROUP g ADD USER a, b is read like ROLE a, b
+                    self._apply_role_options(&name, &tokens[3..]);
+                }
+            }
+            ("COMMENT", "ON") if keyword(2) == "ROLE" && keyword(4) == "IS" => {
+                let name = tokens[3].identifier();
+                let comment = tokens.get(5).and_then(Token::string);
+                if let Some(role) = self.roles.iter_mut().find(|r| r.name == name) {
+                    role.description = comment;
+                }
+            }
+            ("GRANT", _) => self._apply_grant(&tokens[1..], false),
+            ("REVOKE", _) => {
+                // Only an option is revoked, the grant stays
+                if matches!(keyword(1).as_str(), "GRANT" | "ADMIN" | "INHERIT" | "SET") && keyword(2) == "OPTION" {
+                    return;
+                }
+                self._apply_grant(&tokens[1..], true)
+            }
+            ("ALTER", _) => {
+                // Owners have every privilege on their objects
+                let n = tokens.len();
+                if n > 4 && keyword(n - 3) == "OWNER" && keyword(n - 2) == "TO" {
+                    let type_len = object_type_len(&tokens[1..n - 3]);
+                    let mut object = &tokens[1 + type_len..n - 3];
+                    if object.first().map(Token::keyword).as_deref() == Some("ONLY") {
+                        object = &object[1..];
+                    }
+                    if type_len > 0 && !object.is_empty() {
+                        let object_type = render(&tokens[1..1 + type_len]);
+                        self._grant_privileges(&["OWNER".to_string()], &object_type, &[render(object)], &[tokens[n - 1].identifier()], false);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn _warning(&mut self, attribute: Option<&str>, problem: &str) {
+        self.diagnostics.push(LoadDiagnostic::warning(&self.source, Some(self.statement_index), attribute, problem));
+    }
+
+    fn _apply_role_options(&mut self, name: &str, options: &[Token]) {
+        let mut warnings = Vec::new();
+        let role = match self.roles.iter_mut().find(|r| r.name == name) {
+            Some(role) => role,
+            // ALTER ROLE of a role created elsewhere
+            None => {
+                let position = SourcePosition::new(&self.source, self.statement_index);
+                self.roles.push(Role::new(name, false, position));
+                self.roles.last_mut().unwrap()
+            }
+        };
+        let mut in_roles = Vec::new();
+        let mut member_roles = Vec::new();
+
+        let mut i = 0;
+        while i < options.len() {
+            let option = options[i].keyword();
+            match option.as_str() {
+                "LOGIN" => role.login = true,
+                "NOLOGIN" => role.login = false,
+                "INHERIT" => role.inherit = true,
+                "NOINHERIT" => role.inherit = false,
+                "PASSWORD" | "ENCRYPTED" => {
+                    if option == "ENCRYPTED" {
+                        i += 1;
+                    }
+                    role.password = options.get(i + 1).and_then(Token::string).is_some();
+                    i += 1;
+                }
+                "VALID" => {
+                    // "infinity" means no expiration
+                    let value = options.get(i + 2).and_then(Token::string);
+                    role.valid_until = value.as_deref().and_then(parse_iso8601);
+                    if role.valid_until.is_none() && !value.as_deref().is_some_and(|v| v.eq_ignore_ascii_case("infinity")) {
+                        warnings.push(format!("VALID UNTIL {} of role {} is not a timestamp, ignored", value.unwrap_or_default(), name));
+                    }
+                    i += 2;
+                }
+                "CONNECTION" => {
+                    role.connection_limit = options.get(i + 2).map(|t| t.keyword());
+                    i += 2;
+                }
+                // The rest sets configuration parameters or renames the role
+                "SET" | "RESET" | "RENAME" => break,
+                // ALTER ROLE a IN DATABASE d SET ...
+                "IN" if !matches!(options.get(i + 1).map(Token::keyword).as_deref(), Some("ROLE" | "GROUP")) => break,
+                "IN" | "ROLE" | "USER" | "ADMIN" => {
+                    // IN ROLE a, b / IN GROUP a, b (role is member of) and ROLE a, b / ADMIN a, b (members of role)
+                    if option == "IN" {
+                        i += 1;
+                    }
+                    let (names, consumed) = identifier_list(options.get(i + 1..).unwrap_or_default());
+                    if option == "IN" { in_roles.extend(names) } else { member_roles.extend(names) }
+                    i += consumed;
+                }
+                _ => {
+                    let attribute = option.strip_prefix("NO").unwrap_or(&option);
+                    if ROLE_ATTRIBUTES.contains(&attribute) {
+                        role.attributes.retain(|a| a != attribute);
+                        if !option.starts_with("NO") {
+                            role.attributes.push(option.clone());
+                        }
+                    }
+                }
+            }
+            i += 1;
+        }
+        for w in warnings {
+            self._warning(Some("VALID UNTIL"), &w);
+        }
+        for r in in_roles {
+            self._add_membership(&r, name);
+        }
+        for m in member_roles {
+            self._add_membership(name, &m);
+        }
+    }
+
+    // GRANT/REVOKE without the first keyword: either roles to roles, or privileges on objects
+    fn _apply_grant(&mut self, tokens: &[Token], revoke: bool) {
+        let to = if revoke { "FROM" } else { "TO" };
+        let to_index = match top_level_position(tokens, to) {
+            Some(i) => i,
+            None => {
+                self._warning(None, &format!("{} without {}, statement ignored", if revoke { "REVOKE" } else { "GRANT" }, to));
+                return;
+            }
+        };
+        let (grantees, _) = identifier_list(&tokens[to_index + 1..]);
+        let grantees: Vec<String> = grantees.into_iter().filter(|g| g != "CURRENT_USER" && g != "SESSION_USER").collect();
+
+        match top_level_position(&tokens[..to_index], "ON") {
+            Some(on_index) => {
+                // Column lists are kept: "SELECT(id, name)"
+                let privileges: Vec<String> = split_top_level(&tokens[..on_index]).iter()
+                    .map(|p| {
+                        let p = render(p);
+                        let (name, columns) = p.split_at(p.find('(').unwrap_or(p.len()));
+                        format!("{}{}", name.to_uppercase().replace(" PRIVILEGES", ""), columns)
+                    })
+                    .collect();
+                let object_tokens = &tokens[on_index + 1..to_index];
+                let type_len = object_type_len(object_tokens);
+                // "ALL TABLES IN SCHEMA x" is kept as a single object
+                let (object_type, objects) = if object_tokens.first().map(Token::keyword).as_deref() == Some("ALL") {
+                    ("ALL".to_string(), vec![render(&object_tokens[1..])])
+                } else if type_len == 0 {
+                    ("TABLE".to_string(), split_top_level(object_tokens).iter().map(|o| render(o)).collect())
+                } else {
+                    (render(&object_tokens[..type_len]), split_top_level(&object_tokens[type_len..]).iter().map(|o| render(o)).collect())
+                };
+                self._grant_privileges(&privileges, &object_type, &objects, &grantees, revoke);
+            }
+            None => {
+                let (roles, _) = identifier_list(&tokens[..to_index]);
+                for role in roles {
+                    for grantee in &grantees {
+                        if revoke {
+                            self.memberships.retain(|(r, m)| !(r == &role && m == grantee));
+                        } else {
+                            self._add_membership(&role, grantee);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    fn _grant_privileges(&mut self, privileges: &[String], object_type: &str, objects: &[String], grantees: &[String], revoke: bool) {
+        for object in objects {
+            if revoke && privileges.iter().any(|p| p == "ALL") {
+                // REVOKE ALL removes any privilege on the object
+                let ids: Vec<String> = self.privileges.iter()
+                    .filter(|(_, p)| p.object_type == object_type && &p.object == object && p.database == self.database && p.privilege != "OWNER")
+                    .map(|(id, _)| id.clone())
+                    .collect();
+                self.grants.retain(|(id, grantee)| !(ids.contains(id) && grantees.contains(grantee)));
+                continue;
+            }
+            for privilege in privileges {
+                let unique_id = match &self.database {
+                    Some(db) => format!("{}: {} ON {} {}", db, privilege, object_type, object),
+                    None => format!("{} ON {} {}", privilege, object_type, object),
+                };
+                if revoke {
+                    self.grants.retain(|(id, grantee)| !(id == &unique_id && grantees.contains(grantee)));
+                    continue;
+                }
+                if !self.privileges.iter().any(|(id, _)| id == &unique_id) {
+                    self.privileges.push((unique_id.clone(), Privilege { privilege: privilege.clone(), object_type: object_type.to_string(),
+                        object: object.clone(), database: self.database.clone() }));
+                }
+                for grantee in grantees {
+                    if !self.grants.contains(&(unique_id.clone(), grantee.clone())) {
+                        self.grants.push((unique_id.clone(), grantee.clone()));
+                    }
+                }
+            }
+        }
+    }
+    fn _add_membership(&mut self, role: &str, member: &str) {
+        let membership = (role.to_string(), member.to_string());
+        if !self.memberships.contains(&membership) {
+            self.memberships.push(membership);
+        }
+    }
+
+    // Non-login roles, and login roles other roles are members of
+    fn is_group(&self, name: &str) -> bool {
+        self.roles.iter().any(|r| r.name == name && !r.login) || self.memberships.iter().any(|(r, _)| r == name)
+    }
+    // Direct access of a role: roles, privileges, role attributes and PUBLIC.
+    // Attributes aren't inherited, but members can SET ROLE to a role having them
+    fn memberof(&self, name: &str) -> Vec<String> {
+        let mut memberof: Vec<String> = self.memberships.iter().filter(|(_, m)| m == name).map(|(r, _)| r.clone()).collect();
+        memberof.extend(self.grants.iter().filter(|(_, g)| g == name).map(|(id, _)| id.clone()));
+        if let Some(role) = self.roles.iter().find(|r| r.name == name) {
+            memberof.extend(role.attributes.iter().cloned());
+            if role.login && self.grants.iter().any(|(_, g)| g == PUBLIC) {
+                memberof.push(PUBLIC.to_string());
+            }
+        }
+        memberof
+    }
+    fn members(&self, unique_id: &str) -> Vec<String> {
+        if unique_id == PUBLIC {
+            return self.roles.iter().filter(|r| r.login).map(|r| r.name.clone()).collect();
+        }
+        let mut members: Vec<String> = self.memberships.iter().filter(|(r, _)| r == unique_id).map(|(_, m)| m.clone()).collect();
+        members.extend(self.grants.iter().filter(|(id, _)| id == unique_id).map(|(_, g)| g.clone()));
+        members.extend(self.roles.iter().filter(|r| r.attributes.iter().any(|a| a == unique_id)).map(|r| r.name.clone()));
+        members
+    }
+    fn entitlement(&self, unique_id: &str, description: Option<String>, other_attributes: HashMap<String, Option<AttributeValue>>) -> EntitlementDTO {
+        let members = self.members(unique_id);
+        let member_groups = members.iter().filter(|m| self.is_group(m) || *m == PUBLIC).cloned().collect();
+        // Memberships of login roles are their account's, only group roles are nested
+        let memberof = if self.is_group(unique_id) || unique_id == PUBLIC { self.memberof(unique_id) } else { Vec::new() };
+        EntitlementDTO {
+            unique_id: unique_id.to_string(),
+            alternate_ids: Vec::new(),
+            sids: Vec::new(),
+            display_name: None,
+            description,
+            created: None,
+            memberof: Some(memberof),
+            all_indirect_memberof: Vec::new(),
+            members: Some(members),
+            member_groups: Some(member_groups),
+            ou: None,
+            other_attributes,
+            ts_owners: None,
+            position: None,
+        }
+    }
+}
+
+// Words that can make up an object type in GRANT ... ON and ALTER ... OWNER TO
+const OBJECT_TYPE_WORDS: [&str; 22] = ["TABLE", "SEQUENCE", "DATABASE", "SCHEMA", "FUNCTION", "PROCEDURE", "ROUTINE", "LANGUAGE",
+    "LARGE", "OBJECT", "TABLESPACE", "TYPE", "DOMAIN", "FOREIGN", "DATA", "WRAPPER", "SERVER", "VIEW", "MATERIALIZED",
+    "PARAMETER", "PROCEDURAL", "AGGREGATE"];
+fn object_type_len(tokens: &[Token]) -> usize {
+    tokens.iter().take_while(|t| OBJECT_TYPE_WORDS.contains(&t.keyword().as_str())).count()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    // Unquoted identifiers, keywords and numbers
+    Word(String),
+    QuotedIdentifier(String),
+    Str(String),
+    Punct(char),
+}
+impl Token {
+    fn keyword(&self) -> String {
+        match self {
+            Token::Word(w) => w.to_uppercase(),
+            _ => String::new(),
+        }
+    }
+    // Unquoted identifiers are folded to lower case, PUBLIC is kept as the keyword
+    fn identifier(&self) -> String {
+        match self {
+            Token::Word(w) if w.eq_ignore_ascii_case(PUBLIC) || w.eq_ignore_ascii_case("CURRENT_USER") || w.eq_ignore_ascii_case("SESSION_USER") => w.to_uppercase(),
+            Token::Word(w) => w.to_lowercase(),
+            Token::QuotedIdentifier(s) | Token::Str(s) => s.clone(),
+            Token::Punct(c) => c.to_string(),
+        }
+    }
+    fn string(&self) -> Option<String> {
+        match self {
+            Token::Str(s) => Some(s.clone()),
+            _ => None,
+        }
+    }
+}
+
+enum Statement {
+    Sql(Vec<Token>),
+    // psql \connect, gives the database of the following statements
+    Connect(String),
+}
+
+// Splits a dump into statements. Handles comments, quoted identifiers, strings, dollar quoting,
+// psql meta-commands and the data of COPY ... FROM stdin
+fn split_statements(content: &str) -> Vec<Statement> {
+    let chars: Vec<char> = content.chars().collect();
+    let mut statements = Vec::new();
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut i = 0;
+
+    let read_quoted = |i: &mut usize, quote: char| -> String {
+        let mut s = String::new();
+        *i += 1;
+        while *i < chars.len() {
+            if chars[*i] == quote {
+                if chars.get(*i + 1) == Some(&quote) {
+                    s.push(quote);
+                    *i += 2;
+                    continue;
+                }
+                *i += 1;
+                break;
+            }
+            s.push(chars[*i]);
+            *i += 1;
+        }
+        s
+    };
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '-' && chars.get(i + 1) == Some(&'-') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c == '/' && chars.get(i + 1) == Some(&'*') {
+            let mut depth = 0;
+            while i < chars.len() {
+                if chars[i] == '/' && chars.get(i + 1) == Some(&'*') {
+                    depth += 1;
+                    i += 2;
+                } else if chars[i] == '*' && chars.get(i + 1) == Some(&'/') {
+                    depth -= 1;
+                    i += 2;
+                    if depth == 0 {
+                        break;
+                    }
+                } else {
+                    i += 1;
+                }
+            }
+        } else if c == '\\' && tokens.is_empty() {
+            // Meta-command, up to the end of the line
+            let start = i;
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            let line: String = chars[start..i].iter().collect();
+            let mut args = line.split_whitespace();
+            if matches!(args.next(), Some("\\connect" | "\\c")) {
+                if let Some(database) = args.find(|a| !a.starts_with('-')) {
+                    let database = database.trim_matches('"');
+                    let database = database.strip_prefix("dbname=").unwrap_or(database).trim_matches('\'');
+                    statements.push(Statement::Connect(database.to_string()));
+                }
+            }
+        } else if c == '\'' {
+            let s = read_quoted(&mut i, '\'');
+            tokens.push(Token::Str(s));
+        } else if c == '"' {
+            let s = read_quoted(&mut i, '"');
+            tokens.push(Token::QuotedIdentifier(s));
+        } else if c == '$' {
+            // Dollar quoted string ($$...$$ or $tag$...$tag$)
+            let tag_end = chars[i + 1..].iter().position(|c| !(c.is_alphanumeric() || *c == '_')).map(|p| i + 1 + p);
+            match tag_end.filter(|e| chars[*e] == '$') {
+                Some(e) => {
+                    let tag = &chars[i..=e];
+                    let body_start = e + 1;
+                    let body_end = (body_start..chars.len())
+                        .find(|j| chars[*j..].starts_with(tag))
+                        .unwrap_or(chars.len());
+                    tokens.push(Token::Str(chars[body_start..body_end].iter().collect()));
+                    i = (body_end + tag.len()).min(chars.len());
+                }
+                None => {
+                    tokens.push(Token::Punct('$'));
+                    i += 1;
+                }
+            }
+        } else if c.is_alphanumeric() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '$') {
+                i += 1;
+            }
+            tokens.push(Token::Word(chars[start..i].iter().collect()));
+        } else if c == ';' {
+            i += 1;
+            let statement = std::mem::take(&mut tokens);
+            let is_copy_from_stdin = statement.first().map(Token::keyword).as_deref() == Some("COPY")
+                && statement.last().map(Token::keyword).as_deref() == Some("STDIN");
+            statements.push(Statement::Sql(statement));
+            if is_copy_from_stdin {
+                // Data lines end with "\."
+                while i < chars.len() {
+                    let line_end = chars[i..].iter().position(|c| *c == '\n').map(|p| i + p).unwrap_or(chars.len());
+                    let line: String = chars[i..line_end].iter().collect();
+                    i = line_end + 1;
+                    if line.trim_end() == "\\." {
+                        break;
+                    }
+                }
+            }
+        } else {
+            tokens.push(Token::Punct(c));
+            i += 1;
+        }
+    }
+    if !tokens.is_empty() {
+        statements.push(Statement::Sql(tokens));
+    }
+    statements
+}
+
+// Position of a keyword outside parentheses
+fn top_level_position(tokens: &[Token], keyword: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, t) in tokens.iter().enumerate() {
+        match t {
+            Token::Punct('(') => depth += 1,
+            Token::Punct(')') => depth -= 1,
+            Token::Word(_) if depth == 0 && t.keyword() == keyword => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+// Splits on commas outside parentheses
+fn split_top_level(tokens: &[Token]) -> Vec<&[Token]> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, t) in tokens.iter().enumerate() {
+        match t {
+            Token::Punct('(') => depth += 1,
+            Token::Punct(')') => depth -= 1,
+            Token::Punct(',') if depth == 0 => {
+                parts.push(&tokens[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if start < tokens.len() {
+        parts.push(&tokens[start..]);
+    }
+    parts
+}
+// "a, GROUP b, "C" WITH ADMIN OPTION" -> [a, b, C] and the number of tokens read
+fn identifier_list(tokens: &[Token]) -> (Vec<String>, usize) {
+    let mut names = Vec::new();
+    let mut expect_name = true;
+    let mut i = 0;
+    while i < tokens.len() {
+        match &tokens[i] {
+            Token::Word(_) if expect_name && tokens[i].keyword() == "GROUP" => {}
+            Token::Word(_) | Token::QuotedIdentifier(_) if expect_name => {
+                names.push(tokens[i].identifier());
+                expect_name = false;
+            }
+            Token::Punct(',') if !expect_name => expect_name = true,
+            _ => break,
+        }
+        i += 1;
+    }
+    (names, i)
+}
+// Object names and privileges as written in the dump, identifiers quoted only when needed
+fn render(tokens: &[Token]) -> String {
+    let mut s = String::new();
+    for (i, t) in tokens.iter().enumerate() {
+        let text = match t {
+            Token::Word(w) => w.clone(),
+            Token::QuotedIdentifier(q) => format!("\"{}\"", q),
+            Token::Str(v) => format!("'{}'", v),
+            Token::Punct(c) => c.to_string(),
+        };
+        let no_space = i == 0
+            || matches!(t, Token::Punct('.' | ',' | ')'))
+            || matches!(tokens[i - 1], Token::Punct('.' | '('))
+            || (matches!(t, Token::Punct('(')) && matches!(tokens[i - 1], Token::Word(_) | Token::QuotedIdentifier(_)));
+        if !no_space {
+            s.push(' ');
+        }
+        s.push_str(&text);
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Token::*;
+
+    fn word(w: &str) -> Token {
+        Word(w.to_string())
+    }
+    fn sql(content: &str) -> Vec<Vec<Token>> {
+        split_statements(content).into_iter()
+            .filter_map(|s| match s {
+                Statement::Sql(tokens) => Some(tokens),
+                Statement::Connect(_) => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn quoted_identifiers_and_strings() {
+        let statements = sql(r#"CREATE ROLE "Mixed ""Case"";role" PASSWORD 'it''s;secret';"#);
+        assert_eq!(statements, vec![vec![word("CREATE"), word("ROLE"), QuotedIdentifier("Mixed \"Case\";role".to_string()),
+            word("PASSWORD"), Str("it's;secret".to_string())]]);
+        // Quoted identifiers keep their case, unquoted ones are folded
+        assert_eq!(statements[0][2].identifier(), "Mixed \"Case\";role");
+        assert_eq!(word("Alice").identifier(), "alice");
+        assert_eq!(word("public").identifier(), "PUBLIC");
+    }
+
+    #[test]
+    fn dollar_quoting() {
+        let statements = sql("COMMENT ON ROLE a IS $$x; 'y'$$; CREATE FUNCTION f() AS $body$ BEGIN; $$ $body$; GRANT b TO a;");
+        assert_eq!(statements.len(), 3);
+        assert_eq!(statements[0].last(), Some(&Str("x; 'y'".to_string())));
+        assert_eq!(statements[1].last(), Some(&Str(" BEGIN; $$ ".to_string())));
+        assert_eq!(statements[2], vec![word("GRANT"), word("b"), word("TO"), word("a")]);
+        // A lone $ (positional parameter) is punctuation
+        assert_eq!(sql("SELECT $1;"), vec![vec![word("SELECT"), Punct('$'), word("1")]]);
+    }
+
+    #[test]
+    fn comments_meta_commands_and_copy_data() {
+        let content = "-- comment; not a statement\n\\connect -reuse-previous=on \"dbname='sales'\"\n\
+            /* nested /* comment; */ still comment; */ GRANT a TO b;\n\
+            COPY t (c) FROM stdin;\nx;y\n\\.\nCREATE ROLE c;";
+        let statements = split_statements(content);
+        assert!(matches!(&statements[0], Statement::Connect(db) if db == "sales"));
+        let sql: Vec<Vec<Token>> = statements.into_iter().skip(1)
+            .filter_map(|s| match s { Statement::Sql(tokens) => Some(tokens), _ => None })
+            .collect();
+        assert_eq!(sql.len(), 3);
+        assert_eq!(sql[0], vec![word("GRANT"), word("a"), word("TO"), word("b")]);
+        assert_eq!(sql[2], vec![word("CREATE"), word("ROLE"), word("c")]);
+    }
+
+    #[test]
+    fn identifier_lists() {
+        let tokens = &sql(r#"a, GROUP b, "C" WITH ADMIN OPTION"#)[0];
+        assert_eq!(identifier_list(tokens), (vec!["a".to_string(), "b".to_string(), "C".to_string()], 6));
+        // Column privileges are written like function calls
+        assert_eq!(render(&sql(r#"SELECT (c1, "C2") ON s.t"#)[0]), r#"SELECT(c1, "C2") ON s.t"#);
+    }
+
+    #[test]
+    fn role_options() {
+        let mut cluster = Cluster::default();
+        let content = "CREATE ROLE alice LOGIN IN ROLE staff ROLE bob;\n\
+            ALTER ROLE alice IN DATABASE sales SET search_path TO sales;\n\
+            ALTER ROLE alice SET role TO admin;\n\
+            ALTER ROLE alice RENAME TO carol;\n\
+            CREATE ROLE x IN;\n\
+            CREATE ROLE y IN GROUP;";
+        for tokens in sql(content) {
+            cluster.apply(&tokens);
+        }
+        assert_eq!(cluster.memberships, vec![("staff".to_string(), "alice".to_string()), ("alice".to_string(), "bob".to_string())]);
+        assert!(cluster.roles.iter().any(|r| r.name == "alice" && r.login));
+        assert!(cluster.roles.iter().any(|r| r.name == "x") && cluster.roles.iter().any(|r| r.name == "y"));
+    }
+}
@@ -0,0 +1,253 @@
+use std::collections::{HashMap, HashSet};
+use serde_json::{Map, Value};
+use anyhow::Result;
+
+use super::dtos::{AccountDTO, AttributeValue, EntitlementDTO};
+use super::traits::TargetSystemConnector;
+use super::diagnostics::{LoadDiagnostic, ObjectDiagnostics, SourcePosition};
+use super::json::{self, Paging, PagedFile, get_date, get_string};
+use super::timestamps::parse_iso8601;
+use super::nesting;
+
+const ENTERPRISE_USER_SCHEMA: &str = "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User";
+
+// SCIM 2.0 exports of SaaS applications (RFC 7643/7644).
+// Files can hold a ListResponse, an array of ListResponses (one per page) or a plain array of resources.
+// Accounts and groups are identified by their SCIM id, which is what group members and user groups refer to
+#[derive(Debug, Clone)]
+pub struct ScimConnector {
+    pub users_fps: Vec<String>,
+    pub groups_fps: Vec<String>,
+
+    // Additional attributes to keep, looked up in the core schema first and then in the enterprise extension
+    pub other_user_attributes: Vec<String>,
+    pub other_group_attributes: Vec<String>,
+}
+impl ScimConnector {
+    pub fn load_scim_users(&self) -> Result<Vec<AccountDTO>> {
+        let groups = json::read_paged_files(&self.groups_fps, Paging::ScimListResponse)?;
+        self._load_scim_users(&groups, &mut Vec::new())
+    }
+    fn _load_scim_users(&self, groups: &[PagedFile], diagnostics: &mut Vec<LoadDiagnostic>) -> Result<Vec<AccountDTO>> {
+        let users = json::read_paged_files(&self.users_fps, Paging::ScimListResponse)?;
+        for (fp, resources) in &users {
+            for (index, user) in resources.iter().enumerate() {
+                let diag = &mut ObjectDiagnostics { source: fp, object_index: index, diagnostics };
+                check_resource(user, diag);
+                if let Some(active) = user.get("active").filter(|v| !v.is_null() && ScimConnector::_read_bool(v).is_none()) {
+                    diag.warning("active", &format!("{} is not a boolean, ignored", active));
+                }
+            }
+        }
+        let groups: Vec<&Map<String, Value>> = groups.iter().flat_map(|(_, resources)| resources).collect();
+        let group_ids: HashSet<String> = groups.iter().filter_map(|g| get_string(g, "id")).collect();
+
+        // Memberships are often only listed on one side, groups' members are added to the users' groups
+        let mut memberof_from_groups: HashMap<String, Vec<String>> = HashMap::new();
+        for group in &groups {
+            if let Some(group_id) = get_string(group, "id") {
+                for member in multi_valued(group, "members") {
+                    if let Some(member_id) = get_string(member, "value").filter(|id| !ScimConnector::_is_group(member, id, &group_ids)) {
+                        memberof_from_groups.entry(member_id).or_default().push(group_id.clone());
+                    }
+                }
+            }
+        }
+
+        let accounts = users.iter()
+            .flat_map(|(fp, resources)| resources.iter().enumerate().map(|(index, user)| (SourcePosition::new(fp, index), user)))
+            .map(|(position, user)| {
+                let mut a = self._parse_user(user, position);
+                // Groups inherited through nesting ("type": "indirect") are calculated again from the groups' members
+                let mut memberof: Vec<String> = multi_valued(user, "groups")
+                    .filter(|g| !get_string(g, "type").is_some_and(|t| t.eq_ignore_ascii_case("indirect")))
+                    .filter_map(|g| get_string(g, "value"))
+                    .collect();
+                for group_id in memberof_from_groups.remove(&a.unique_id).unwrap_or_default() {
+                    if !memberof.contains(&group_id) {
+                        memberof.push(group_id);
+                    }
+                }
+                a.memberof = Some(memberof);
+                a
+            })
+            .collect();
+        Ok(accounts)
+    }
+    fn _parse_user(&self, user: &Map<String, Value>, position: SourcePosition) -> AccountDTO {
+        let enterprise = user.get(ENTERPRISE_USER_SCHEMA).and_then(Value::as_object);
+        let meta = user.get("meta").and_then(Value::as_object);
+
+        let display_name = get_string(user, "displayName")
+            .or_else(|| user.get("name").and_then(Value::as_object).and_then(|n| get_string(n, "formatted")))
+            .or_else(|| get_string(user, "userName"));
+
+        let mut other_attributes = HashMap::new();
+        other_attributes.insert("userName".to_string(), get_string(user, "userName").map(AttributeValue::from));
+        other_attributes.insert("externalId".to_string(), get_string(user, "externalId").map(AttributeValue::from));
+        other_attributes.insert("userType".to_string(), get_string(user, "userType").map(AttributeValue::from));
+        other_attributes.insert("email".to_string(), ScimConnector::_primary_value(user, "emails").map(AttributeValue::from));
+        for k in ["employeeNumber", "costCenter", "organization", "division", "department"] {
+            other_attributes.insert(k.to_string(), enterprise.and_then(|e| get_string(e, k)).map(AttributeValue::from));
+        }
+        // Manager is a complex attribute, its id ("value") is kept
+        let manager = enterprise.and_then(|e| e.get("manager")).and_then(Value::as_object);
+        other_attributes.insert("manager".to_string(), manager.and_then(|m| get_string(m, "value")).map(AttributeValue::from));
+        other_attributes.insert("managerDisplayName".to_string(), manager.and_then(|m| get_string(m, "displayName")).map(AttributeValue::from));
+        for k in &self.other_user_attributes {
+            let value = user.get(k).or_else(|| enterprise.and_then(|e| e.get(k)));
+            other_attributes.insert(k.clone(), value.and_then(AttributeValue::from_json));
+        }
+
+        AccountDTO {
+            unique_id: get_string(user, "id").unwrap_or("No ID".to_string()), // Preferable to continue with partial data
+            alternate_ids: Vec::new(),
+            sids: Vec::new(),
+            display_name,
+            description: get_string(user, "title"),
+            created: meta.and_then(|m| get_date(m, "created")),
+            last_logon: None,
+            password_last_set: None,
+            expiration_date: None,
+            enabled: user.get("active").and_then(ScimConnector::_read_bool),
+            deleted: None,
+            locked: None,
+            flags: None,
+            memberof: None,
+            ou: None,
+            manager: manager.and_then(|m| get_string(m, "value")),
+            other_attributes,
+            position: Some(position),
+        }
+    }
+
+    pub fn load_scim_groups(&self) -> Result<Vec<EntitlementDTO>> {
+        let groups = json::read_paged_files(&self.groups_fps, Paging::ScimListResponse)?;
+        self._load_scim_groups(&groups, &mut Vec::new())
+    }
+    fn _load_scim_groups(&self, groups: &[PagedFile], diagnostics: &mut Vec<LoadDiagnostic>) -> Result<Vec<EntitlementDTO>> {
+        for (fp, resources) in groups {
+            for (index, group) in resources.iter().enumerate() {
+                let diag = &mut ObjectDiagnostics { source: fp, object_index: index, diagnostics };
+                check_resource(group, diag);
+                let ignored_members = multi_valued(group, "members").filter(|m| get_string(m, "value").is_none()).count();
+                if ignored_members > 0 {
+                    diag.warning("members", &format!("{} members without value, ignored", ignored_members));
+                }
+            }
+        }
+        let group_ids: HashSet<String> = groups.iter()
+            .flat_map(|(_, resources)| resources)
+            .filter_map(|g| get_string(g, "id"))
+            .collect();
+
+        let mut ents: Vec<EntitlementDTO> = groups.iter()
+            .flat_map(|(fp, resources)| resources.iter().enumerate().map(|(index, group)| (SourcePosition::new(fp, index), group)))
+            .map(|(position, group)| self._parse_group(group, &group_ids, position))
+            .collect();
+
+        // Post-processing (populate memberof and nested groups crawling)
+        nesting::calculate_memberof(&mut ents);
+        Ok(nesting::calculate_indirect_memberof(ents))
+    }
+    fn _parse_group(&self, group: &Map<String, Value>, group_ids: &HashSet<String>, position: SourcePosition) -> EntitlementDTO {
+        let meta = group.get("meta").and_then(Value::as_object);
+
+        let mut members = Vec::new();
+        let mut member_groups = Vec::new();
+        for member in multi_valued(group, "members") {
+            if let Some(member_id) = get_string(member, "value") {
+                if ScimConnector::_is_group(member, &member_id, group_ids) {
+                    member_groups.push(member_id.clone());
+                }
+                members.push(member_id);
+            }
+        }
+
+        let mut other_attributes = HashMap::new();
+        other_attributes.insert("externalId".to_string(), get_string(group, "externalId").map(AttributeValue::from));
+        for k in &self.other_group_attributes {
+            other_attributes.insert(k.clone(), group.get(k).and_then(AttributeValue::from_json));
+        }
+
+        EntitlementDTO {
+            unique_id: get_string(group, "id").unwrap_or("No ID".to_string()), // Preferable to continue with partial data
+            alternate_ids: Vec::new(),
+            sids: Vec::new(),
+            display_name: get_string(group, "displayName"),
+            description: None,
+            created: meta.and_then(|m| get_date(m, "created")),
+            memberof: None,
+            all_indirect_memberof: Vec::new(),
+            members: Some(members),
+            member_groups: Some(member_groups),
+            ou: None,
+            other_attributes,
+            ts_owners: None,
+            position: Some(position),
+        }
+    }
+
+    // Members carry an optional "type" ("User" or "Group"). Without it, the id is looked up among the groups read
+    fn _is_group(member: &Map<String, Value>, member_id: &String, group_ids: &HashSet<String>) -> bool {
+        match member.get("type").and_then(Value::as_str) {
+            Some(t) => t.eq_ignore_ascii_case("Group"),
+            None => group_ids.contains(member_id),
+        }
+    }
+    // Primary value of a multi-valued attribute (emails, phoneNumbers), or the first one
+    fn _primary_value(resource: &Map<String, Value>, attribute: &str) -> Option<String> {
+        let values: Vec<&Map<String, Value>> = multi_valued(resource, attribute).collect();
+        values.iter()
+            .find(|v| v.get("primary").and_then(Value::as_bool) == Some(true))
+            .or(values.first())
+            .and_then(|v| get_string(v, "value"))
+    }
+    // Some providers send booleans as strings
+    fn _read_bool(value: &Value) -> Option<bool> {
+        match value {
+            Value::Bool(b) => Some(*b),
+            Value::String(s) => s.parse().ok(),
+            _ => None,
+        }
+    }
+}
+impl TargetSystemConnector for ScimConnector {
+    fn load_accounts(&self) -> Result<Vec<AccountDTO>> {
+        self.load_scim_users()
+    }
+    fn load_entitlements(&self) -> Result<Vec<EntitlementDTO>> {
+        self.load_scim_groups()
+    }
+    fn load_accounts_with_diagnostics(&self, diagnostics: &mut Vec<LoadDiagnostic>) -> Result<Vec<AccountDTO>> {
+        let groups = json::read_paged_files(&self.groups_fps, Paging::ScimListResponse)?;
+        self._load_scim_users(&groups, diagnostics)
+    }
+    fn load_entitlements_with_diagnostics(&self, diagnostics: &mut Vec<LoadDiagnostic>) -> Result<Vec<EntitlementDTO>> {
+        let groups = json::read_paged_files(&self.groups_fps, Paging::ScimListResponse)?;
+        self._load_scim_groups(&groups, diagnostics)
+    }
+    // The users' groups are completed from the groups' members, the group files are read once
+    fn load_all_with_diagnostics(&self, diagnostics: &mut Vec<LoadDiagnostic>) -> Result<(Vec<AccountDTO>, Vec<EntitlementDTO>)> {
+        let groups = json::read_paged_files(&self.groups_fps, Paging::ScimListResponse)?;
+        Ok((self._load_scim_users(&groups, diagnostics)?, self._load_scim_groups(&groups, diagnostics)?))
+    }
+}
+
+// Values of the common attributes the mapping drops
+fn check_resource(resource: &Map<String, Value>, diag: &mut ObjectDiagnostics) {
+    if get_string(resource, "id").is_none() {
+        diag.error("id", "Missing id, loaded as \"No ID\"");
+    }
+    let created = resource.get("meta").and_then(|m| m.get("created")).filter(|v| !v.is_null());
+    if let Some(created) = created.filter(|c| c.as_str().and_then(parse_iso8601).is_none()) {
+        diag.warning("meta.created", &format!("Unparseable date {}, ignored", created));
+    }
+}
+fn multi_valued<'a>(resource: &'a Map<String, Value>, attribute: &str) -> impl Iterator<Item = &'a Map<String, Value>> {
+    resource.get(attribute)
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_object)
+}
@@ -0,0 +1,160 @@
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, TimeZone, Utc};
+
+// Timestamp formats found in the exports read by the connectors.
+// Time of day and UTC offset are kept; values without offset are taken as UTC
+
+// Seconds from 1601-01-01 (Windows FILETIME epoch) to 1970-01-01
+const FILETIME_EPOCH_OFFSET: i64 = 11_644_473_600;
+
+// Any of the encodings used by AD and LDAP exports:
+// "/Date(1680344430000)/", "/Date(-62135596800000)/", "/Date(1680344430000+0200)/" (.NET JSON),
+// "133248180300000000" (FILETIME), "20230401102030.0Z" (LDAP generalized time) and ISO 8601
+pub fn parse_timestamp(value: &str) -> Option<DateTime<FixedOffset>> {
+    let value = value.trim();
+    if let Some(inner) = value.strip_prefix("/Date(").and_then(|v| v.strip_suffix(")/")) {
+        return parse_dotnet_date(inner);
+    }
+    if value.len() > 14 && value.bytes().all(|b| b.is_ascii_digit()) {
+        return value.parse().ok().and_then(from_filetime);
+    }
+    parse_generalized_time(value).or_else(|| parse_iso8601(value))
+}
+
+// 100-nanosecond intervals since 1601-01-01 UTC (lastLogonTimestamp, pwdLastSet, accountExpires...).
+// 0 and i64::MAX mean "never"
+pub fn from_filetime(filetime: i64) -> Option<DateTime<FixedOffset>> {
+    if filetime <= 0 || filetime == i64::MAX {
+        return None;
+    }
+    let secs = filetime / 10_000_000 - FILETIME_EPOCH_OFFSET;
+    let nanos = (filetime % 10_000_000) * 100;
+    Utc.timestamp_opt(secs, nanos as u32).single().map(DateTime::from)
+}
+
+// Milliseconds since the Unix epoch (can be negative), optionally followed by the offset of the local time ("+0100").
+// The milliseconds are UTC, the offset only tells how to display them
+fn parse_dotnet_date(inner: &str) -> Option<DateTime<FixedOffset>> {
+    let (millis, offset) = match inner.get(1..).and_then(|rest| rest.find(['+', '-'])) {
+        Some(p) => (&inner[..p + 1], Some(&inner[p + 1..])),
+        None => (inner, None),
+    };
+    let utc = Utc.timestamp_millis_opt(millis.parse().ok()?).single()?;
+    match offset {
+        Some(o) => Some(utc.with_timezone(&parse_offset(o)?)),
+        None => Some(utc.into()),
+    }
+}
+
+// "YYYYMMDDHHMMSS[.fraction](Z|+HHMM|-HHMM)"
+fn parse_generalized_time(value: &str) -> Option<DateTime<FixedOffset>> {
+    let naive = NaiveDateTime::parse_from_str(value.get(..14)?, "%Y%m%d%H%M%S").ok()?;
+    let mut rest = &value[14..];
+    if let Some(fraction) = rest.strip_prefix(['.', ',']) {
+        rest = fraction.trim_start_matches(|c: char| c.is_ascii_digit());
+    }
+    let offset = match rest {
+        "" | "Z" => FixedOffset::east_opt(0)?,
+        _ => parse_offset(rest)?,
+    };
+    offset.from_local_datetime(&naive).single()
+}
+
+// "+0100", "-05:30", "+01"
+fn parse_offset(value: &str) -> Option<FixedOffset> {
+    let sign = match value.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let digits: String = value[1..].chars().filter(|c| *c != ':').collect();
+    let hours: i32 = digits.get(..2)?.parse().ok()?;
+    let minutes: i32 = match digits.get(2..) {
+        Some("") | None => 0,
+        Some(m) => m.parse().ok()?,
+    };
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+// ISO 8601 / RFC 3339 ("2023-04-01T10:20:30Z", "2023-04-01T10:20:30.1234567+02:00", "2023-04-01 10:20:30+00", "2023-04-01")
+pub fn parse_iso8601(value: &str) -> Option<DateTime<FixedOffset>> {
+    let value = value.trim();
+    if let Ok(dt) = DateTime::parse_from_rfc3339(value) {
+        return Some(dt);
+    }
+    let value = value.replacen(' ', "T", 1);
+    if let Ok(dt) = DateTime::parse_from_str(&value, "%Y-%m-%dT%H:%M:%S%.f%#z") {
+        return Some(dt);
+    }
+    if let Ok(dt) = NaiveDateTime::parse_from_str(&value, "%Y-%m-%dT%H:%M:%S%.f") {
+        return Some(utc(dt));
+    }
+    NaiveDate::parse_from_str(&value, "%Y-%m-%d").ok()
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .map(utc)
+}
+
+// Dates with a configured chrono format, which may or may not include time and offset
+pub fn parse_with_format(value: &str, format: &str) -> Option<DateTime<FixedOffset>> {
+    let value = value.trim();
+    if let Ok(dt) = DateTime::parse_from_str(value, format) {
+        return Some(dt);
+    }
+    if let Ok(dt) = NaiveDateTime::parse_from_str(value, format) {
+        return Some(utc(dt));
+    }
+    NaiveDate::parse_from_str(value, format).ok()
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .map(utc)
+}
+
+pub fn utc(naive: NaiveDateTime) -> DateTime<FixedOffset> {
+    Utc.from_utc_datetime(&naive).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rfc3339(value: &str) -> Option<String> {
+        parse_timestamp(value).map(|d| d.to_rfc3339())
+    }
+
+    #[test]
+    fn filetime() {
+        assert_eq!(rfc3339("133248180300000000").as_deref(), Some("2023-04-01T10:20:30+00:00"));
+        assert_eq!(from_filetime(133248180301234567).map(|d| d.to_rfc3339()).as_deref(), Some("2023-04-01T10:20:30.123456700+00:00"));
+        // Never
+        assert_eq!(from_filetime(0), None);
+        assert_eq!(from_filetime(i64::MAX), None);
+    }
+
+    #[test]
+    fn dotnet_dates() {
+        assert_eq!(rfc3339("/Date(1680344430000)/").as_deref(), Some("2023-04-01T10:20:30+00:00"));
+        assert_eq!(rfc3339("/Date(-62135596800000)/").as_deref(), Some("0001-01-01T00:00:00+00:00"));
+        // The milliseconds are UTC whatever the offset, which only changes the display
+        assert_eq!(rfc3339("/Date(1680344430000+0200)/").as_deref(), Some("2023-04-01T12:20:30+02:00"));
+        assert_eq!(rfc3339("/Date(1680344430000-0530)/").as_deref(), Some("2023-04-01T04:50:30-05:30"));
+        assert_eq!(parse_timestamp("/Date(1680344430000+0200)/"), parse_timestamp("/Date(1680344430000)/"));
+        assert_eq!(rfc3339("/Date(abc)/"), None);
+    }
+
+    #[test]
+    fn generalized_time() {
+        assert_eq!(rfc3339("20230401102030.0Z").as_deref(), Some("2023-04-01T10:20:30+00:00"));
+        assert_eq!(rfc3339("20230401102030Z").as_deref(), Some("2023-04-01T10:20:30+00:00"));
+        assert_eq!(rfc3339("20230401122030,5+0200").as_deref(), Some("2023-04-01T12:20:30+02:00"));
+        assert_eq!(rfc3339("20230401102030-05").as_deref(), Some("2023-04-01T10:20:30-05:00"));
+        assert_eq!(rfc3339("20231301102030Z"), None);
+    }
+
+    #[test]
+    fn iso8601() {
+        assert_eq!(rfc3339("2023-04-01T10:20:30Z").as_deref(), Some("2023-04-01T10:20:30+00:00"));
+        assert_eq!(rfc3339("2023-04-01T10:20:30.1234567+02:00").as_deref(), Some("2023-04-01T10:20:30.123456700+02:00"));
+        assert_eq!(rfc3339("2023-04-01 10:20:30+00").as_deref(), Some("2023-04-01T10:20:30+00:00"));
+        assert_eq!(rfc3339("2023-04-01T10:20:30").as_deref(), Some("2023-04-01T10:20:30+00:00"));
+        assert_eq!(rfc3339("2023-04-01").as_deref(), Some("2023-04-01T00:00:00+00:00"));
+        assert_eq!(rfc3339("yesterday"), None);
+    }
+}
@@ -0,0 +1,47 @@
+use std::fmt::Debug;
+use anyhow::Result;
+use crate::model::ts::HistoryRecord;
+
+use super::dtos::{AccountDTO, EntitlementDTO, IdentityDTO};
+use super::diagnostics::LoadDiagnostic;
+
+// Source of accounts and entitlements of a TargetSystem.
+// Each implementation knows how to read one export format and map it into the DTOs,
+// the TargetSystem takes care of the rest (lookups, indirect access, history).
+pub trait TargetSystemConnector: Debug + Send + Sync {
+    fn load_accounts(&self) -> Result<Vec<AccountDTO>>;
+    fn load_entitlements(&self) -> Result<Vec<EntitlementDTO>>;
+
+    // Same loads, also reporting the problems found in the data. Used by the TargetSystem.
+    // Connectors that don't check their data just load
+    fn load_accounts_with_diagnostics(&self, _diagnostics: &mut Vec<LoadDiagnostic>) -> Result<Vec<AccountDTO>> {
+        self.load_accounts()
+    }
+    fn load_entitlements_with_diagnostics(&self, _diagnostics: &mut Vec<LoadDiagnostic>) -> Result<Vec<EntitlementDTO>> {
+        self.load_entitlements()
+    }
+    // Accounts and entitlements of one load of the TargetSystem.
+    // Connectors reading the same files for both override it to read them only once
+    fn load_all_with_diagnostics(&self, diagnostics: &mut Vec<LoadDiagnostic>) -> Result<(Vec<AccountDTO>, Vec<EntitlementDTO>)> {
+        Ok((self.load_accounts_with_diagnostics(diagnostics)?, self.load_entitlements_with_diagnostics(diagnostics)?))
+    }
+
+    // Optional history records. The link_key of each record is the unique_id of the object it belongs to
+    fn load_account_history(&self) -> Result<Vec<HistoryRecord>> {
+        Ok(Vec::new())
+    }
+    fn load_entitlement_history(&self) -> Result<Vec<HistoryRecord>> {
+        Ok(Vec::new())
+    }
+}
+
+// Source of identities (HR extracts, contractor registers...).
+// Several sources can be configured, their records are merged by unique_id in Iga.
+pub trait IdentitySourceConnector: Debug + Send + Sync {
+    fn read_identities(&self) -> Result<Vec<IdentityDTO>>;
+
+    // Same read, also reporting the problems found in the data. Used by Iga
+    fn read_identities_with_diagnostics(&self, _diagnostics: &mut Vec<LoadDiagnostic>) -> Result<Vec<IdentityDTO>> {
+        self.read_identities()
+    }
+}
@@ -1,5 +1,8 @@
 pub mod dtos;
 pub mod iga;
 pub mod ts;
-
+pub mod survivorship;
+pub mod duplicates;
+pub mod delta;
+pub mod dn;
 
@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+use super::iga::Identity;
+
+// Attributes whose change makes an identity a mover, unless configured otherwise
+pub const DEFAULT_MOVER_ATTRIBUTES: [&str; 4] = ["department", "manager_key", "employee_type", "cost_centre"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Movement {
+    // New in the current snapshot, or active again after leaving (rehire)
+    Joiner,
+    // One of the mover attributes changed
+    Mover,
+    // Became a leaver, or missing from the current snapshot
+    Leaver,
+}
+
+#[derive(Debug, Clone)]
+pub struct AttributeChange {
+    pub attribute: String,
+    pub previous: String,
+    pub current: String,
+}
+
+// Difference found for one identity between the previous and the current HR snapshot
+#[derive(Debug, Clone)]
+pub struct IdentityChange {
+    pub unique_id: String,
+    pub movement: Movement,
+    // Changes of the mover attributes, also for joiners and leavers present in both snapshots
+    pub changes: Vec<AttributeChange>,
+    // Leaver no longer in the HR extract
+    pub removed: bool,
+}
+
+// Classifies every identity that changed between the two snapshots, ordered by unique_id.
+// Identities that left in both snapshots are not reported again
+pub fn compare_snapshots(previous: &HashMap<String, Identity>, current: &HashMap<String, Identity>, mover_attributes: &[String]) -> Vec<IdentityChange> {
+    let mut identity_changes = Vec::new();
+
+    for (uid, ident) in current {
+        let (movement, changes) = match previous.get(uid) {
+            None if ident.is_leaver() => (Movement::Leaver, Vec::new()),
+            None => (Movement::Joiner, Vec::new()),
+            Some(prev) => {
+                let changes = _attribute_changes(prev, ident, mover_attributes);
+                match (prev.is_leaver(), ident.is_leaver()) {
+                    (false, true) => (Movement::Leaver, changes),
+                    (true, false) => (Movement::Joiner, changes),
+                    (false, false) if !changes.is_empty() => (Movement::Mover, changes),
+                    _ => continue,
+                }
+            }
+        };
+        // Clone ok, only the key
+        identity_changes.push(IdentityChange { unique_id: uid.clone(), movement, changes, removed: false });
+    }
+    for (uid, prev) in previous {
+        if !current.contains_key(uid) && !prev.is_leaver() {
+            identity_changes.push(IdentityChange { unique_id: uid.clone(), movement: Movement::Leaver, changes: Vec::new(), removed: true });
+        }
+    }
+
+    identity_changes.sort_by(|a, b| a.unique_id.cmp(&b.unique_id));
+    identity_changes
+}
+
+fn _attribute_changes(previous: &Identity, current: &Identity, mover_attributes: &[String]) -> Vec<AttributeChange> {
+    mover_attributes.iter()
+        .map(|attribute| AttributeChange {
+            attribute: attribute.clone(),
+            previous: previous.get_attribute(attribute),
+            current: current.get_attribute(attribute),
+        })
+        .filter(|change| change.previous != change.current)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use super::*;
+    use crate::connectors::dtos::{IdentityDTO, IdentityStatus};
+
+    fn snapshot(identities: &[(&str, IdentityStatus, Option<&str>, &str)]) -> HashMap<String, Identity> {
+        identities.iter()
+            .map(|(uid, status, termination_date, department)| {
+                let dto = IdentityDTO {
+                    unique_id: uid.to_string(),
+                    status: Some(*status),
+                    termination_date: termination_date.map(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").unwrap()),
+                    attributes: HashMap::from([("department".to_string(), department.to_string())]),
+                    ..Default::default()
+                };
+                (uid.to_string(), Identity::from_identity_dto(dto))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn joiners_movers_and_leavers() {
+        use IdentityStatus::*;
+        let previous = snapshot(&[
+            ("SAME", Active, None, "IT"),
+            ("MOVER", Active, None, "IT"),
+            ("LEAVER", Active, None, "IT"),
+            ("FUTURE", Active, None, "IT"),
+            ("REMOVED", Active, None, "IT"),
+            ("GONE", Terminated, None, "IT"),
+            ("REHIRE", Terminated, Some("2020-01-31"), "IT"),
+            ("STILL_GONE", Terminated, None, "IT"),
+        ]);
+        let current = snapshot(&[
+            ("SAME", Active, None, "IT"),
+            ("MOVER", Active, None, "Sales"),
+            // A termination still to come isn't a leaver yet, the department change makes a mover
+            ("FUTURE", Terminated, Some("2999-12-31"), "HR"),
+            ("LEAVER", Terminated, Some("2020-01-31"), "IT"),
+            ("REHIRE", Active, None, "Sales"),
+            ("STILL_GONE", Terminated, None, "IT"),
+            ("JOINER", Active, None, "IT"),
+            ("JOINED_GONE", Terminated, None, "IT"),
+        ]);
+
+        let changes = compare_snapshots(&previous, &current, &["department".to_string()]);
+        let found: Vec<(&str, Movement, usize, bool)> = changes.iter()
+            .map(|c| (c.unique_id.as_str(), c.movement, c.changes.len(), c.removed))
+            .collect();
+        assert_eq!(found, vec![
+            ("FUTURE", Movement::Mover, 1, false),
+            ("JOINED_GONE", Movement::Leaver, 0, false),
+            ("JOINER", Movement::Joiner, 0, false),
+            ("LEAVER", Movement::Leaver, 0, false),
+            ("MOVER", Movement::Mover, 1, false),
+            ("REHIRE", Movement::Joiner, 1, false),
+            ("REMOVED", Movement::Leaver, 0, true),
+        ]);
+        assert_eq!((changes[4].changes[0].previous.as_str(), changes[4].changes[0].current.as_str()), ("IT", "Sales"));
+    }
+
+    #[test]
+    fn legacy_disabled_status() {
+        use IdentityStatus::*;
+        // Disabled records of the default status mapping are leavers once past their termination date only
+        let previous = snapshot(&[("A", Active, None, "IT"), ("B", Active, None, "IT")]);
+        let current = snapshot(&[("A", Suspended, None, "IT"), ("B", Suspended, Some("2020-01-31"), "IT")]);
+        let found: Vec<(String, Movement)> = compare_snapshots(&previous, &current, &[]).into_iter()
+            .map(|c| (c.unique_id, c.movement))
+            .collect();
+        assert_eq!(found, vec![("B".to_string(), Movement::Leaver)]);
+    }
+}
@@ -0,0 +1,280 @@
+use std::collections::HashMap;
+use crate::connectors::dtos::{AccountDTO, EntitlementDTO};
+
+// Distinguished names as defined by RFC 4514 ("CN=Doe\, John,OU=Users,DC=corp,DC=com").
+// Escaped characters (\, \+ \2C...), quoted values, multi-valued RDNs (CN=a+UID=b) and ';' separators are understood
+#[derive(Debug, Clone, PartialEq)]
+pub struct DistinguishedName {
+    // From the leaf to the root. Each RDN holds one or more (type, value) pairs
+    pub rdns: Vec<Vec<(String, String)>>,
+}
+impl DistinguishedName {
+    pub fn parse(dn: &str) -> Option<Self> {
+        let chars: Vec<char> = dn.chars().collect();
+        let mut rdns = Vec::new();
+        let mut rdn = Vec::new();
+        let mut i = 0;
+
+        loop {
+            // Attribute type
+            let start = i;
+            while i < chars.len() && chars[i] != '=' {
+                if matches!(chars[i], ',' | ';' | '+') {
+                    return None;
+                }
+                i += 1;
+            }
+            let attribute_type: String = chars[start..i].iter().collect::<String>().trim().to_string();
+            if i == chars.len() || attribute_type.is_empty() {
+                return None;
+            }
+            i += 1;
+
+            // Attribute value
+            while i < chars.len() && chars[i] == ' ' {
+                i += 1;
+            }
+            let mut bytes: Vec<u8> = Vec::new();
+            // Length of the value without the unescaped trailing spaces
+            let mut significant_len = 0;
+            if chars.get(i) == Some(&'"') {
+                i += 1;
+                while i < chars.len() && chars[i] != '"' {
+                    if chars[i] == '\\' && i + 1 < chars.len() {
+                        i += 1;
+                    }
+                    push_char(&mut bytes, chars[i]);
+                    i += 1;
+                }
+                i += 1;
+                significant_len = bytes.len();
+                while i < chars.len() && chars[i] == ' ' {
+                    i += 1;
+                }
+            } else {
+                while i < chars.len() && !matches!(chars[i], ',' | ';' | '+') {
+                    if chars[i] == '\\' {
+                        let hex: String = chars.get(i + 1..i + 3).map(|h| h.iter().collect()).unwrap_or_default();
+                        match u8::from_str_radix(&hex, 16) {
+                            Ok(byte) if hex.chars().all(|c| c.is_ascii_hexdigit()) => {
+                                bytes.push(byte);
+                                i += 3;
+                            }
+                            _ => {
+                                push_char(&mut bytes, *chars.get(i + 1)?);
+                                i += 2;
+                            }
+                        }
+                        significant_len = bytes.len();
+                        continue;
+                    }
+                    push_char(&mut bytes, chars[i]);
+                    if chars[i] != ' ' {
+                        significant_len = bytes.len();
+                    }
+                    i += 1;
+                }
+            }
+            bytes.truncate(significant_len);
+            rdn.push((attribute_type, String::from_utf8_lossy(&bytes).into_owned()));
+
+            match chars.get(i) {
+                Some('+') => {}
+                Some(',' | ';') => rdns.push(std::mem::take(&mut rdn)),
+                None => {
+                    rdns.push(rdn);
+                    break;
+                }
+                Some(_) => return None,
+            }
+            i += 1;
+        }
+        Some(Self { rdns })
+    }
+
+    // Value of the first attribute of the leaf RDN (the CN of "CN=Doe\, John,OU=Users,...")
+    pub fn leaf_value(&self) -> Option<&str> {
+        self.rdns.first().and_then(|rdn| rdn.first()).map(|(_, v)| v.as_str())
+    }
+    pub fn parent(&self) -> Option<Self> {
+        if self.rdns.len() < 2 {
+            return None;
+        }
+        Some(Self { rdns: self.rdns[1..].to_vec() })
+    }
+    // Comparable form: types and values in lower case, RDN attributes sorted, minimal escaping
+    pub fn normalized(&self) -> String {
+        self.rdns.iter()
+            .map(|rdn| {
+                let mut attributes: Vec<String> = rdn.iter()
+                    .map(|(t, v)| format!("{}={}", t.to_lowercase(), escape_value(&v.to_lowercase())))
+                    .collect();
+                attributes.sort();
+                attributes.join("+")
+            })
+            .collect::<Vec<String>>()
+            .join(",")
+    }
+}
+impl std::fmt::Display for DistinguishedName {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let rdns: Vec<String> = self.rdns.iter()
+            .map(|rdn| rdn.iter().map(|(t, v)| format!("{}={}", t, escape_value(v))).collect::<Vec<String>>().join("+"))
+            .collect();
+        write!(f, "{}", rdns.join(","))
+    }
+}
+
+fn push_char(bytes: &mut Vec<u8>, c: char) {
+    let mut buf = [0; 4];
+    bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+}
+fn escape_value(value: &str) -> String {
+    let mut escaped = String::new();
+    let last = value.chars().count().saturating_sub(1);
+    for (i, c) in value.chars().enumerate() {
+        let needs_escape = matches!(c, ',' | '+' | '"' | '\\' | '<' | '>' | ';' | '=')
+            || (i == 0 && (c == '#' || c == ' '))
+            || (i == last && c == ' ');
+        if needs_escape {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// Key under which a reference is looked up: normalized DN, or the identifier in lower case
+// (sAMAccountNames are case insensitive, GUIDs may come with braces and in upper case)
+pub fn reference_key(value: &str) -> String {
+    let value = value.trim();
+    if value.contains('=') {
+        if let Some(dn) = DistinguishedName::parse(value) {
+            return dn.normalized();
+        }
+    }
+    value.trim_start_matches('{').trim_end_matches('}').to_lowercase()
+}
+
+// SID of a member that belongs to another domain: "CN=S-1-5-21-...-1104,CN=ForeignSecurityPrincipals,DC=corp,DC=com",
+// or the bare SID when the export holds SIDs
+pub fn foreign_principal_sid(reference: &str) -> Option<String> {
+    let reference = reference.trim();
+    if reference.starts_with("S-1-") {
+        return Some(reference.to_uppercase());
+    }
+    let dn = DistinguishedName::parse(reference)?;
+    dn.leaf_value()
+        .filter(|v| v.starts_with("S-1-"))
+        .map(|v| v.to_uppercase())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ObjectRef {
+    Account(String),
+    Entitlement(String),
+}
+
+// Every identifier an object of a target system can be referred to by, pointing to its unique_id
+#[derive(Debug, Default)]
+pub struct ObjectIndex(HashMap<String, ObjectRef>);
+impl ObjectIndex {
+    pub fn from_dtos(accounts: &[AccountDTO], entitlements: &[EntitlementDTO]) -> Self {
+        let mut index = ObjectIndex(HashMap::new());
+        for a in accounts {
+            for id in std::iter::once(&a.unique_id).chain(&a.alternate_ids).chain(&a.sids) {
+                index._insert(id, ObjectRef::Account(a.unique_id.clone()));
+            }
+        }
+        for e in entitlements {
+            for id in std::iter::once(&e.unique_id).chain(&e.alternate_ids).chain(&e.sids) {
+                index._insert(id, ObjectRef::Entitlement(e.unique_id.clone()));
+            }
+        }
+        index
+    }
+    // The first object keeps an identifier shared by several ones
+    fn _insert(&mut self, id: &str, object: ObjectRef) {
+        self.0.entry(reference_key(id)).or_insert(object);
+    }
+
+    pub fn resolve(&self, reference: &str) -> Option<&ObjectRef> {
+        self.0.get(&reference_key(reference))
+    }
+    pub fn resolve_uid(&self, reference: &str) -> Option<&str> {
+        match self.resolve(reference)? {
+            ObjectRef::Account(uid) | ObjectRef::Entitlement(uid) => Some(uid),
+        }
+    }
+    pub fn resolve_account(&self, reference: &str) -> Option<&str> {
+        match self.resolve(reference) {
+            Some(ObjectRef::Account(uid)) => Some(uid),
+            _ => None,
+        }
+    }
+    pub fn resolve_entitlement(&self, reference: &str) -> Option<&str> {
+        match self.resolve(reference) {
+            Some(ObjectRef::Entitlement(uid)) => Some(uid),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(dn: &DistinguishedName) -> Vec<Vec<(&str, &str)>> {
+        dn.rdns.iter().map(|rdn| rdn.iter().map(|(t, v)| (t.as_str(), v.as_str())).collect()).collect()
+    }
+
+    #[test]
+    fn escaped_special_characters() {
+        let dn = DistinguishedName::parse(r"CN=Doe\, John,OU=R\+D,DC=corp,DC=com").unwrap();
+        assert_eq!(values(&dn), vec![vec![("CN", "Doe, John")], vec![("OU", "R+D")], vec![("DC", "corp")], vec![("DC", "com")]]);
+        assert_eq!(dn.leaf_value(), Some("Doe, John"));
+    }
+
+    #[test]
+    fn hex_pairs() {
+        // UTF-8 bytes of "č" and "ć", then an escaped comma
+        let dn = DistinguishedName::parse(r"CN=Lu\C4\8Di\C4\87,OU=a\2Cb").unwrap();
+        assert_eq!(values(&dn), vec![vec![("CN", "Lučić")], vec![("OU", "a,b")]]);
+    }
+
+    #[test]
+    fn multi_valued_rdn() {
+        let dn = DistinguishedName::parse("CN=Doe+UID=jdoe,DC=corp").unwrap();
+        assert_eq!(values(&dn), vec![vec![("CN", "Doe"), ("UID", "jdoe")], vec![("DC", "corp")]]);
+        // Order of the attributes and case don't matter once normalized
+        let other = DistinguishedName::parse("uid=JDOE+cn=doe,dc=CORP").unwrap();
+        assert_eq!(dn.normalized(), other.normalized());
+    }
+
+    #[test]
+    fn quotes_spaces_and_semicolons() {
+        let dn = DistinguishedName::parse(r#"CN="Doe, John" ; OU = Users ,DC=a\ "#).unwrap();
+        assert_eq!(values(&dn), vec![vec![("CN", "Doe, John")], vec![("OU", "Users")], vec![("DC", "a ")]]);
+    }
+
+    #[test]
+    fn invalid_dns() {
+        assert_eq!(DistinguishedName::parse("CN=a,b"), None);
+        assert_eq!(DistinguishedName::parse("=a"), None);
+        assert_eq!(DistinguishedName::parse("jdoe"), None);
+    }
+
+    #[test]
+    fn display_round_trip() {
+        let dn = DistinguishedName::parse(r"CN=\#1\, Doe+UID=a\=b,OU=\ x\ ,DC=corp").unwrap();
+        assert_eq!(DistinguishedName::parse(&dn.to_string()), Some(dn));
+    }
+
+    #[test]
+    fn references() {
+        assert_eq!(reference_key("CN=Doe\\, John,OU=Users,DC=Corp"), reference_key("cn=doe\\2c john, ou=users, dc=corp"));
+        assert_eq!(reference_key("{0A1B-C2}"), "0a1b-c2");
+        assert_eq!(foreign_principal_sid("CN=S-1-5-21-1-2-3-1104,CN=ForeignSecurityPrincipals,DC=corp"), Some("S-1-5-21-1-2-3-1104".to_string()));
+        assert_eq!(foreign_principal_sid("CN=Doe,DC=corp"), None);
+    }
+}
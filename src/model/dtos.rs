@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 
+use crate::connectors::dtos::AttributeValue;
 use super::ts::HistoryRecord;
 
 
@@ -52,6 +53,14 @@ impl IdentityData {
     }
 }
 
+// Joiner, mover or leaver, with the access the identity holds now
+#[derive(Debug, Clone)]
+pub struct IdentityChangeData {
+    pub identity: IdentityData,
+    pub movement: String,
+    pub changes: Vec<String>,
+}
+
 #[derive(Debug, Clone)]
 pub struct AccountData {
     pub uid: String,
@@ -62,9 +71,12 @@ pub struct AccountData {
     pub account_type: String, 
     pub ou: String,
     pub enabled: String,
+    pub flags: String,
     pub syncs_to_ts: String,
     pub syncs_to_account: String,
     pub identity_owners: Vec<String>,
+    // Ordered by attribute name
+    pub other_attributes: Vec<(String, Option<AttributeValue>)>,
     pub macheo: bool,
     pub history: Vec<HistoryRecord>,
 }
@@ -81,6 +93,8 @@ pub struct EntitlementData {
     pub syncs_to_ts: String,
     pub syncs_to_entitlement: String,
     pub identity_owners: Vec<String>,
+    // Ordered by attribute name
+    pub other_attributes: Vec<(String, Option<AttributeValue>)>,
     pub macheo: bool,
     pub history: Vec<HistoryRecord>,
 }
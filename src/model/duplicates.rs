@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+use anyhow::{Result, anyhow};
+use crate::connectors::dtos::{AccountDTO, EntitlementDTO, IdentityDTO};
+use crate::connectors::diagnostics::{LoadDiagnostic, SourcePosition};
+
+// Placeholder of the connectors for objects without ID
+pub const NO_ID: &str = "No ID";
+
+// What to keep when a source has several records with the same unique_id.
+// Records without ID are never duplicates of each other, each one is kept as "No ID (<file>, object <position>)".
+// The default keeps the last record, as loads did before duplicates were reported: choose Merge to combine them
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum DuplicatePolicy {
+    // The first record is kept, the others are dropped
+    FirstWins,
+    // Each record replaces the previous one
+    #[default]
+    LastWins,
+    // Missing values of the first record are taken from the others, lists are joined
+    Merge,
+    // The load fails
+    Reject,
+}
+
+// Records read from a source, keyed by unique_id
+pub trait Keyed {
+    fn key(&self) -> &str;
+    fn set_key(&mut self, key: String);
+    // Where the record was read, if the connector knows it
+    fn position(&self) -> Option<&SourcePosition>;
+    // Completes self with the values of a duplicate
+    fn merge(&mut self, other: Self);
+}
+
+// Applies the policy to the records of one source, keeping the order in which they were read.
+// Every duplicate is reported at its position in the files, with the position of the record it collides with.
+// Duplicates the policy resolves are warnings, only rejected ones are errors.
+// Objects a connector builds itself (roles, policies...) have no position, they are reported on the source
+pub fn resolve_duplicates<T: Keyed>(records: Vec<T>, policy: DuplicatePolicy, source: &str, diagnostics: &mut Vec<LoadDiagnostic>) -> Result<Vec<T>> {
+    let mut resolved: Vec<T> = Vec::with_capacity(records.len());
+    // unique_id -> index in resolved
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    let mut rejected = Vec::new();
+
+    for (index, mut record) in records.into_iter().enumerate() {
+        if record.key().is_empty() || record.key() == NO_ID {
+            let position = record.position().cloned().unwrap_or(SourcePosition::new(source, index));
+            record.set_key(format!("{} ({})", NO_ID, position));
+            resolved.push(record);
+            continue;
+        }
+        let i = match seen.get(record.key()) {
+            Some(i) => *i,
+            None => {
+                // Clone ok, only the key
+                seen.insert(record.key().to_string(), resolved.len());
+                resolved.push(record);
+                continue;
+            }
+        };
+        // The kept record is the first one, or the last one that replaced it
+        let kept_position = resolved[i].position().map_or("earlier in the load".to_string(), |p| format!("at {}", p));
+        let problem = |outcome: &str| format!("Duplicate unique_id '{}', also {}: {}", record.key(), kept_position, outcome);
+        let (file, object_index) = record.position().map_or((source, None), |p| (p.source.as_str(), Some(p.object_index)));
+        match policy {
+            DuplicatePolicy::FirstWins => {
+                diagnostics.push(LoadDiagnostic::warning(file, object_index, None, &problem("record dropped")));
+            }
+            DuplicatePolicy::LastWins => {
+                diagnostics.push(LoadDiagnostic::warning(file, object_index, None, &problem("replaces the previous record")));
+                resolved[i] = record;
+            }
+            DuplicatePolicy::Merge => {
+                diagnostics.push(LoadDiagnostic::warning(file, object_index, None, &problem("records merged")));
+                resolved[i].merge(record);
+            }
+            DuplicatePolicy::Reject => {
+                let d = LoadDiagnostic::error(file, object_index, None, &problem("load rejected"));
+                rejected.push(d.to_string());
+                diagnostics.push(d);
+            }
+        }
+    }
+
+    if !rejected.is_empty() {
+        return Err(anyhow!("{} duplicate unique_ids found (duplicates are rejected):\n{}", rejected.len(), rejected.join("\n")));
+    }
+    Ok(resolved)
+}
+
+fn fill<T>(value: &mut Option<T>, other: Option<T>) {
+    if value.is_none() {
+        *value = other;
+    }
+}
+fn fill_string(value: &mut String, other: String) {
+    if value.is_empty() {
+        *value = other;
+    }
+}
+fn join(list: &mut Vec<String>, other: Vec<String>) {
+    for v in other {
+        if !list.contains(&v) {
+            list.push(v);
+        }
+    }
+}
+fn join_option(list: &mut Option<Vec<String>>, other: Option<Vec<String>>) {
+    match (list.as_mut(), other) {
+        (Some(l), Some(o)) => join(l, o),
+        (None, o) => *list = o,
+        _ => {}
+    }
+}
+
+impl Keyed for IdentityDTO {
+    fn key(&self) -> &str {
+        &self.unique_id
+    }
+    fn set_key(&mut self, key: String) {
+        self.unique_id = key;
+    }
+    fn position(&self) -> Option<&SourcePosition> {
+        self.position.as_ref()
+    }
+    fn merge(&mut self, other: Self) {
+        fill_string(&mut self.first_name, other.first_name);
+        fill_string(&mut self.last_name, other.last_name);
+        fill_string(&mut self.email, other.email);
+        fill_string(&mut self.employee_no, other.employee_no);
+        fill_string(&mut self.employee_type, other.employee_type);
+        fill(&mut self.status, other.status);
+        fill_string(&mut self.manager_key, other.manager_key);
+        fill(&mut self.hire_date, other.hire_date);
+        fill(&mut self.termination_date, other.termination_date);
+        for (k, v) in other.attributes {
+            fill_string(self.attributes.entry(k).or_default(), v);
+        }
+    }
+}
+impl Keyed for AccountDTO {
+    fn key(&self) -> &str {
+        &self.unique_id
+    }
+    fn set_key(&mut self, key: String) {
+        self.unique_id = key;
+    }
+    fn position(&self) -> Option<&SourcePosition> {
+        self.position.as_ref()
+    }
+    fn merge(&mut self, other: Self) {
+        join(&mut self.alternate_ids, other.alternate_ids);
+        join(&mut self.sids, other.sids);
+        fill(&mut self.display_name, other.display_name);
+        fill(&mut self.description, other.description);
+        fill(&mut self.created, other.created);
+        fill(&mut self.last_logon, other.last_logon);
+        fill(&mut self.password_last_set, other.password_last_set);
+        fill(&mut self.expiration_date, other.expiration_date);
+        fill(&mut self.enabled, other.enabled);
+        fill(&mut self.deleted, other.deleted);
+        fill(&mut self.locked, other.locked);
+        fill(&mut self.flags, other.flags);
+        join_option(&mut self.memberof, other.memberof);
+        fill(&mut self.ou, other.ou);
+        fill(&mut self.manager, other.manager);
+        for (k, v) in other.other_attributes {
+            fill(self.other_attributes.entry(k).or_default(), v);
+        }
+    }
+}
+impl Keyed for EntitlementDTO {
+    fn key(&self) -> &str {
+        &self.unique_id
+    }
+    fn set_key(&mut self, key: String) {
+        self.unique_id = key;
+    }
+    fn position(&self) -> Option<&SourcePosition> {
+        self.position.as_ref()
+    }
+    fn merge(&mut self, other: Self) {
+        join(&mut self.alternate_ids, other.alternate_ids);
+        join(&mut self.sids, other.sids);
+        fill(&mut self.display_name, other.display_name);
+        fill(&mut self.description, other.description);
+        fill(&mut self.created, other.created);
+        join_option(&mut self.memberof, other.memberof);
+        join(&mut self.all_indirect_memberof, other.all_indirect_memberof);
+        join_option(&mut self.members, other.members);
+        join_option(&mut self.member_groups, other.member_groups);
+        fill(&mut self.ou, other.ou);
+        for (k, v) in other.other_attributes {
+            fill(self.other_attributes.entry(k).or_default(), v);
+        }
+        join_option(&mut self.ts_owners, other.ts_owners);
+    }
+}
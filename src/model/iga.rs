@@ -1,18 +1,37 @@
 use std::collections::HashMap;
 use std::mem;
-use chrono::NaiveDate;
-use serde::Serialize;
+use std::sync::Arc;
+use chrono::{Local, NaiveDate};
 use anyhow::{Result, anyhow};
-use crate::{connectors::identity_xlsx::IdentityXlsxConnector};
-use crate::connectors::dtos::IdentityDTO;
-use super::dtos::{IdentityData, AccountData, EntitlementData, CategoryTotals, CategorizedEntitlements, CategorizedAccounts};
-use super::ts::{TargetSystem, TargetSystemConfig, HistoryRecord};
+use crate::connectors::traits::IdentitySourceConnector;
+use crate::connectors::dtos::{IdentityDTO, IdentityStatus};
+use crate::connectors::diagnostics::{LoadDiagnostic, Severity};
+use super::survivorship::SurvivorshipRules;
+use super::duplicates::{DuplicatePolicy, resolve_duplicates};
+use super::dtos::{IdentityData, IdentityChangeData, AccountData, EntitlementData, CategoryTotals, CategorizedEntitlements, CategorizedAccounts};
+use super::ts::{TargetSystem, TargetSystemConfig, HistoryRecord, ForeignObject};
+use super::dn::foreign_principal_sid;
+use super::delta::{IdentityChange, DEFAULT_MOVER_ATTRIBUTES, compare_snapshots};
+
+// What to do with the problems found in the source data
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum LoadMode {
+    // Any error (data dropped or replaced) fails the load. Warnings are kept in Iga.diagnostics, as in lenient mode
+    Strict,
+    // Problems are kept in Iga.diagnostics and listed in a "Data quality" sheet of the reports
+    #[default]
+    Lenient,
+}
 
 #[derive(Debug)] 
 pub struct IgaConfig {
     identity_sources: IdentitySourceConfig,
     target_systems: Vec<TargetSystemConfig>,
     ts_sync: Option<(String, String)>,
+    load_mode: LoadMode,
+    // Previous HR snapshot, compared with the current one to find joiners, movers and leavers
+    previous_identity_sources: Option<IdentitySourceConfig>,
+    mover_attributes: Vec<String>,
 }
 impl IgaConfig {
     pub fn new(id_s: IdentitySourceConfig) -> Self {
@@ -20,8 +39,23 @@ impl IgaConfig {
             identity_sources: id_s,
             target_systems: Vec::new(),
             ts_sync: None,
+            load_mode: LoadMode::default(),
+            previous_identity_sources: None,
+            mover_attributes: DEFAULT_MOVER_ATTRIBUTES.iter().map(|a| a.to_string()).collect(),
         }
     }
+    pub fn set_load_mode(&mut self, load_mode: LoadMode) {
+        self.load_mode = load_mode;
+    }
+    // Sources of the previous snapshot are read like the current ones, their uids should differ from the current sources'.
+    // Leavers missing from the current snapshot are kept as terminated identities, so their accounts are still matched
+    pub fn set_previous_identity_sources(&mut self, id_s: IdentitySourceConfig) {
+        self.previous_identity_sources = Some(id_s);
+    }
+    // Standard (manager_key, employee_type...) or free-form attribute names
+    pub fn set_mover_attributes(&mut self, attributes: Vec<String>) {
+        self.mover_attributes = attributes;
+    }
     pub fn add_target_system(&mut self, t_s: TargetSystemConfig) {
         self.target_systems.push(t_s);
     }
@@ -30,9 +64,33 @@ impl IgaConfig {
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Default)]
 pub struct IdentitySourceConfig {
-    pub connector: IdentityXlsxConnector,
+    pub sources: Vec<IdentitySource>,
+    pub survivorship: SurvivorshipRules,
+    // Identities read more than once from the same source. Records of different sources are merged by survivorship
+    pub duplicate_policy: DuplicatePolicy,
+}
+impl IdentitySourceConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    // Sources are given precedence in the order they are added, unless a survivorship rule says otherwise
+    pub fn add_source(&mut self, unique_id: &str, connector: Arc<dyn IdentitySourceConnector>) {
+        self.sources.push(IdentitySource { unique_id: unique_id.to_string(), connector });
+    }
+    pub fn add_precedence(&mut self, attribute: &str, source_uids: Vec<String>) {
+        self.survivorship.add_precedence(attribute, source_uids);
+    }
+    pub fn set_duplicate_policy(&mut self, policy: DuplicatePolicy) {
+        self.duplicate_policy = policy;
+    }
+}
+
+#[derive(Debug)]
+pub struct IdentitySource {
+    pub unique_id: String,
+    pub connector: Arc<dyn IdentitySourceConnector>,
 }
 
 #[derive(Debug)] 
@@ -41,6 +99,14 @@ pub struct Iga {
 
     pub identities: HashMap<String, Identity>,           
     pub target_systems: HashMap<String, TargetSystem>,   
+    // Problems found in the identity sources and target systems while loading
+    pub diagnostics: Vec<LoadDiagnostic>,
+    // Only loaded when a previous snapshot is configured
+    pub previous_identities: HashMap<String, Identity>,
+    pub identity_changes: Vec<IdentityChange>,
+    // Leavers of the previous snapshot missing from the current one, terminated. Matched to their accounts only to
+    // report the access they still hold: they are not identities of the current extract and count in no other report
+    pub removed_leavers: HashMap<String, Identity>,
 }
 impl Iga {
     pub fn new (config: IgaConfig) -> Iga {
@@ -48,42 +114,195 @@ impl Iga {
             config,
             identities: HashMap::new(), 
             target_systems: HashMap::new(),
+            diagnostics: Vec::new(),
+            previous_identities: HashMap::new(),
+            identity_changes: Vec::new(),
+            removed_leavers: HashMap::new(),
         }
     }
     pub fn load_all(&mut self) -> Result<()> {
+        self.diagnostics.clear();
         self._load_identities()?;
+        self._check_diagnostics()?;
         self._load_target_systems()?;
+        self._resolve_foreign_principals();
+        Ok(())
+    }
+    // In strict mode, stops the load at the first source with errors
+    fn _check_diagnostics(&self) -> Result<()> {
+        if self.config.load_mode != LoadMode::Strict {
+            return Ok(());
+        }
+        let errors: Vec<&LoadDiagnostic> = self.diagnostics.iter().filter(|d| d.severity == Severity::Error).collect();
+        if !errors.is_empty() {
+            let first: Vec<String> = errors.iter().take(10).map(|d| d.to_string()).collect();
+            return Err(anyhow!("{} errors found in the source data (strict load mode):\n{}{}",
+                errors.len(), first.join("\n"), if errors.len() > 10 { "\n..." } else { "" }));
+        }
         Ok(())
     }
     fn _load_identities(&mut self) -> Result<()> {
-        let identities_read = self.config.identity_sources.connector.read_identities()?;
+        self.identities = Iga::_read_identities(&self.config.identity_sources, &mut self.diagnostics)?;
+
+        if let Some(previous_sources) = &self.config.previous_identity_sources {
+            self.previous_identities = Iga::_read_identities(previous_sources, &mut self.diagnostics)?;
+            self.identity_changes = compare_snapshots(&self.previous_identities, &self.identities, &self.config.mover_attributes);
+
+            // Leavers missing from the extract are kept, terminated, to find the access they still hold
+            for change in self.identity_changes.iter().filter(|c| c.removed) {
+                if let Some(previous) = self.previous_identities.get(&change.unique_id) {
+                    // Clone ok, only identities missing from the extract
+                    let mut identity = previous.clone();
+                    identity.status = Some(IdentityStatus::Terminated);
+                    // Gone already: a termination date still to come doesn't hold anymore, the leaver must count as one
+                    identity.termination_date = identity.termination_date.filter(|d| *d < Local::now().date_naive());
+                    self.removed_leavers.insert(change.unique_id.clone(), identity);
+                }
+            }
+        }
+        Ok(())
+    }
+    fn _read_identities(id_sources: &IdentitySourceConfig, diagnostics: &mut Vec<LoadDiagnostic>) -> Result<HashMap<String, Identity>> {
+        // Groups the records of all sources by unique_id, keeping the order in which sources were configured
+        let mut records_per_uid: HashMap<String, Vec<(String, IdentityDTO)>> = HashMap::new();
+        for source in &id_sources.sources {
+            let dtos = source.connector.read_identities_with_diagnostics(diagnostics)?;
+            for dto in resolve_duplicates(dtos, id_sources.duplicate_policy, &source.unique_id, diagnostics)? {
+                // Clones ok, only keys
+                records_per_uid.entry(dto.unique_id.clone())
+                    .or_default()
+                    .push((source.unique_id.clone(), dto));
+            }
+        }
 
-        let identities: HashMap<String, Identity> = identities_read
-            .into_iter()
-            .map(|dto| {
+        let identities: HashMap<String, Identity> = records_per_uid
+            .into_values()
+            .map(|records| {
+                let (dto, attribute_sources) = id_sources.survivorship.merge(records);
                 // Moves data, no cloning
-                let identity = Identity::from_identity_dto(dto);
+                let mut identity = Identity::from_identity_dto(dto);
+                identity.attribute_sources = attribute_sources;
                 // Clone ok, only key of hashmap
                 (identity.unique_id.clone(), identity)
             })
             .collect();
 
-        self.identities = identities;
-        Ok(())
+        Ok(identities)
     }
     fn _load_target_systems(&mut self) -> Result<()> {
         for ts_config in mem::take(&mut self.config.target_systems) { 
             let mut ts = TargetSystem::new(ts_config);
             ts.load()?;
+            self.diagnostics.extend(ts.diagnostics.iter().cloned());
+            self._check_diagnostics()?;
 
             (ts.config.account_matching_rules)(self, &mut ts);
             (ts.config.entitlements_ownership_rules)(self, &mut ts);
+            self._match_removed_leavers(&mut ts);
             // Cloning ok, is only the key
             self.target_systems.insert(ts.config.unique_id.clone(),ts);
 
         } 
         Ok(())
     }
+    // The rules match accounts and groups to self.identities: they are run again with the removed leavers in their place.
+    // Accounts and groups keep the owners of the current extract only, the leavers keep what they matched
+    fn _match_removed_leavers(&mut self, ts: &mut TargetSystem) {
+        if self.removed_leavers.is_empty() {
+            return;
+        }
+        mem::swap(&mut self.identities, &mut self.removed_leavers);
+        (ts.config.account_matching_rules)(self, ts);
+        (ts.config.entitlements_ownership_rules)(self, ts);
+        mem::swap(&mut self.identities, &mut self.removed_leavers);
+
+        for a in ts.accounts.values_mut() {
+            a.identity_owners.retain(|uid| !self.removed_leavers.contains_key(uid));
+        }
+        for e in ts.entitlements.values_mut() {
+            e.identity_owners.retain(|uid| !self.removed_leavers.contains_key(uid));
+        }
+    }
+    // Members of trusted domains appear in groups as foreign security principals (CN=S-1-5-21-...,CN=ForeignSecurityPrincipals,...).
+    // They are linked here to the account or group holding that SID (objectSid or sIDHistory) in another target system
+    fn _resolve_foreign_principals(&mut self) {
+        // SID -> (object, is an account)
+        let mut objects_by_sid: HashMap<String, (ForeignObject, bool)> = HashMap::new();
+        for (ts_uid, ts) in &self.target_systems {
+            let accounts = ts.accounts.values().map(|a| (&a.unique_id, &a.sids, true));
+            let entitlements = ts.entitlements.values().map(|e| (&e.unique_id, &e.sids, false));
+            for (uid, sids, is_account) in accounts.chain(entitlements) {
+                for sid in sids {
+                    let object = ForeignObject { ts_uid: ts_uid.clone(), unique_id: uid.clone() };
+                    objects_by_sid.entry(sid.to_uppercase()).or_insert((object, is_account));
+                }
+            }
+        }
+
+        // (group, foreign member, member is an account)
+        let mut links: Vec<(ForeignObject, ForeignObject, bool)> = Vec::new();
+        for (ts_uid, ts) in &self.target_systems {
+            for e in ts.entitlements.values() {
+                for member in e.members.iter().flatten() {
+                    let found = foreign_principal_sid(member).and_then(|sid| objects_by_sid.get(&sid));
+                    if let Some((object, is_account)) = found {
+                        if &object.ts_uid != ts_uid {
+                            let group = ForeignObject { ts_uid: ts_uid.clone(), unique_id: e.unique_id.clone() };
+                            links.push((group, object.clone(), *is_account));
+                        }
+                    }
+                }
+            }
+        }
+        for (group, member, is_account) in links {
+            if let Some(e) = self.target_systems.get_mut(&group.ts_uid).and_then(|ts| ts.entitlements.get_mut(&group.unique_id)) {
+                e.foreign_members.push(member.clone());
+            }
+            if let Some(ts) = self.target_systems.get_mut(&member.ts_uid) {
+                if is_account {
+                    if let Some(a) = ts.accounts.get_mut(&member.unique_id) {
+                        a.foreign_memberof.push(group);
+                    }
+                } else if let Some(e) = ts.entitlements.get_mut(&member.unique_id) {
+                    e.foreign_memberof.push(group);
+                }
+            }
+        }
+
+        // Indirect access of the accounts in other target systems: through their own groups, then nesting there.
+        // Only one hop between target systems is followed
+        let mut indirect_access: Vec<(String, String, Vec<ForeignObject>)> = Vec::new();
+        for (ts_uid, ts) in &self.target_systems {
+            for a in ts.accounts.values() {
+                let mut reached: Vec<ForeignObject> = a.memberof.iter().flatten()
+                    .chain(&a.memberof_indirect)
+                    .filter_map(|ent_uid| ts.entitlements.get(ent_uid))
+                    .flat_map(|e| e.foreign_memberof.iter().cloned())
+                    .collect();
+                let groups: Vec<ForeignObject> = a.foreign_memberof.iter().chain(&reached).cloned().collect();
+                for group in groups {
+                    if let Some(e) = self.target_systems.get(&group.ts_uid).and_then(|ts| ts.entitlements.get(&group.unique_id)) {
+                        reached.extend(e.all_indirect_memberof.iter()
+                            .map(|uid| ForeignObject { ts_uid: group.ts_uid.clone(), unique_id: uid.clone() }));
+                    }
+                }
+                let mut indirect: Vec<ForeignObject> = Vec::new();
+                for group in reached {
+                    if !a.foreign_memberof.contains(&group) && !indirect.contains(&group) {
+                        indirect.push(group);
+                    }
+                }
+                if !indirect.is_empty() {
+                    indirect_access.push((ts_uid.clone(), a.unique_id.clone(), indirect));
+                }
+            }
+        }
+        for (ts_uid, acct_uid, indirect) in indirect_access {
+            if let Some(a) = self.target_systems.get_mut(&ts_uid).and_then(|ts| ts.accounts.get_mut(&acct_uid)) {
+                a.foreign_memberof_indirect = indirect;
+            }
+        }
+    }
 
     pub fn add_account_history(&mut self, ts_uid: &str, records: HashMap<String, HistoryRecord>) -> Result<()> {
         match self.target_systems.get_mut(ts_uid) {
@@ -121,6 +340,22 @@ impl Iga {
             .map(|ts| (ts.config.unique_id.clone(), ts.get_persistent_leaver_accounts(&self.identities)))
             .collect()
     }
+    // Joiners, movers and leavers since the previous snapshot. Movers and leavers come with the access they still hold
+    pub fn get_identity_changes_data(&self) -> Vec<IdentityChangeData> {
+        self.identity_changes.iter()
+            .filter_map(|change| {
+                let ident = self.identities.get(&change.unique_id).or_else(|| self.removed_leavers.get(&change.unique_id))?;
+                Some(IdentityChangeData {
+                    identity: ident.to_data(&self.target_systems),
+                    movement: format!("{:?}", change.movement),
+                    changes: change.changes.iter()
+                        .map(|c| format!("{}: '{}' -> '{}'", c.attribute, c.previous, c.current))
+                        .chain(change.removed.then(|| "Not in the HR extract anymore".to_string()))
+                        .collect(),
+                })
+            })
+            .collect()
+    }
     pub fn get_entitlement_count_per_type(&self) ->  Vec<CategoryTotals> {
         self.target_systems.values()
             .map(|ts| ts.get_entitlement_category_totals())
@@ -151,20 +386,42 @@ impl Iga {
 
 trait TargetSystems {
     fn get_accounts_data(&self, accts: &HashMap<String, Vec<String>>) -> HashMap<String, Vec<AccountData>>;
+    fn get_foreign_entitlements_data(&self, ents: &[ForeignObject]) -> Vec<EntitlementData>;
 }
 impl TargetSystems for HashMap<String, TargetSystem> {
     fn get_accounts_data(&self, accts: &HashMap<String, Vec<String>>) -> HashMap<String, Vec<AccountData>> {
         let mut accts_ddtos = HashMap::new();
         for (ts_uid, accts_uids) in accts {
             if let Some(ts) = self.get(ts_uid) {
-                accts_ddtos.insert(ts_uid.clone(), ts.get_accounts_data(accts_uids));
+                let mut accts_data = ts.get_accounts_data(accts_uids);
+                // Access granted in other target systems (trusted domains)
+                for acct_data in &mut accts_data {
+                    if let Some(acct) = ts.accounts.get(&acct_data.uid) {
+                        acct_data.entitlements.extend(self.get_foreign_entitlements_data(&acct.foreign_memberof));
+                        acct_data.indirect_entitlements.extend(self.get_foreign_entitlements_data(&acct.foreign_memberof_indirect));
+                    }
+                }
+                accts_ddtos.insert(ts_uid.clone(), accts_data);
             }
         }
         accts_ddtos
     }
+    fn get_foreign_entitlements_data(&self, ents: &[ForeignObject]) -> Vec<EntitlementData> {
+        let mut ents_data = Vec::new();
+        for ent in ents {
+            if let Some(ts) = self.get(&ent.ts_uid) {
+                for mut ent_data in ts.get_entitlements_data(false, std::slice::from_ref(&ent.unique_id)) {
+                    // The target system is shown, as names are often the same in every domain ("Domain Admins")
+                    ent_data.display_name = format!("{} ({})", ent_data.display_name, ent.ts_uid);
+                    ents_data.push(ent_data);
+                }
+            }
+        }
+        ents_data
+    }
 }
 
-#[derive(Debug)] 
+#[derive(Debug, Clone)] 
 pub struct Identity {
     pub unique_id: String,
     pub first_name: String,
@@ -172,11 +429,13 @@ pub struct Identity {
     pub email: String,
     pub employee_no: String,  
     pub employee_type: String,
-    pub enabled: Option<bool>,
+    pub status: Option<IdentityStatus>,
     pub manager_key: String,  
     pub hire_date: Option<NaiveDate>,
     pub termination_date: Option<NaiveDate>,
     pub attributes: HashMap<String, String>,
+    // Identity source that won each attribute (attribute name -> source uid)
+    pub attribute_sources: HashMap<String, String>,
     
     pub matched_personal_accounts: HashMap<String,Vec<String>>, 
     pub matched_owned_accounts: HashMap<String,Vec<String>>, 
@@ -192,7 +451,7 @@ impl Identity{
             email: dto.email,
             employee_no: dto.employee_no,
             employee_type: dto.employee_type,
-            enabled: dto.enabled,
+            status: dto.status,
 
             manager_key: dto.manager_key,
             hire_date: dto.hire_date,
@@ -203,6 +462,7 @@ impl Identity{
             matched_owned_groups: HashMap::new(),
 
             attributes: dto.attributes,
+            attribute_sources: HashMap::new(),
         }
     }
     pub fn to_data(&self, target_systems: &HashMap<String, TargetSystem>) -> IdentityData {
@@ -235,11 +495,33 @@ impl Identity{
         ents_ddtos
     }
     
+    // Value of a standard or free-form attribute, by the name used in the identity source configuration
+    pub fn get_attribute(&self, name: &str) -> String {
+        match name {
+            "unique_id" => self.unique_id.clone(),
+            "first_name" => self.first_name.clone(),
+            "last_name" => self.last_name.clone(),
+            "email" => self.email.clone(),
+            "employee_no" => self.employee_no.clone(),
+            "employee_type" => self.employee_type.clone(),
+            "status" => self.status.map(|s| format!("{:?}", s)).unwrap_or_default(),
+            "manager_key" => self.manager_key.clone(),
+            "hire_date" => self.hire_date.map(|d| d.to_string()).unwrap_or_default(),
+            "termination_date" => self.termination_date.map(|d| d.to_string()).unwrap_or_default(),
+            _ => self.attributes.get(name).cloned().unwrap_or_default(),
+        }
+    }
+    // Not working at the moment: on leave, suspended, gone or not started yet. Unknown status counts as active
     pub fn is_inactive(&self) -> bool {
-        if let Some(enabled) = self.enabled {
-            !enabled
-        } else {
-            false
+        self.status.is_some_and(|s| s != IdentityStatus::Active)
+    }
+    // Terminated, and past the termination date if there is one. Other inactive statuses need a past termination date,
+    // as disabled records of sources that don't tell terminations apart (the default status mapping) always did
+    pub fn is_leaver(&self) -> bool {
+        let past_termination = self.termination_date.map(|d| d < Local::now().date_naive());
+        match self.status {
+            Some(IdentityStatus::Terminated) => past_termination.unwrap_or(true),
+            _ => self.is_inactive() && past_termination.unwrap_or(false),
         }
     }
 }
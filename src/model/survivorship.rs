@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+use std::mem;
+use crate::connectors::dtos::IdentityDTO;
+
+// Per-attribute precedence of identity sources, used when several sources describe the same unique_id.
+// Attributes without a rule follow the order in which the sources were configured.
+#[derive(Debug, Default)]
+pub struct SurvivorshipRules {
+    pub attribute_precedence: HashMap<String, Vec<String>>,
+}
+impl SurvivorshipRules {
+    pub fn add_precedence(&mut self, attribute: &str, source_uids: Vec<String>) {
+        self.attribute_precedence.insert(attribute.to_string(), source_uids);
+    }
+
+    // Merges all records read for one unique_id. Records are (source uid, dto), in source configuration order.
+    // Returns the surviving identity and, for each attribute, the uid of the source it was taken from
+    pub fn merge(&self, mut records: Vec<(String, IdentityDTO)>) -> (IdentityDTO, HashMap<String, String>) {
+        let mut merged = IdentityDTO::default();
+        let mut attribute_sources = HashMap::new();
+
+        if let Some((_, first)) = records.first() {
+            // Clone ok, only the key
+            merged.unique_id = first.unique_id.clone();
+        }
+
+        // Standard attributes
+        self._survive(&mut records, &mut attribute_sources, "first_name", &mut merged.first_name, |d| &mut d.first_name, |v| !v.is_empty());
+        self._survive(&mut records, &mut attribute_sources, "last_name", &mut merged.last_name, |d| &mut d.last_name, |v| !v.is_empty());
+        self._survive(&mut records, &mut attribute_sources, "email", &mut merged.email, |d| &mut d.email, |v| !v.is_empty());
+        self._survive(&mut records, &mut attribute_sources, "employee_no", &mut merged.employee_no, |d| &mut d.employee_no, |v| !v.is_empty());
+        self._survive(&mut records, &mut attribute_sources, "employee_type", &mut merged.employee_type, |d| &mut d.employee_type, |v| !v.is_empty());
+        self._survive(&mut records, &mut attribute_sources, "status", &mut merged.status, |d| &mut d.status, |v| v.is_some());
+        self._survive(&mut records, &mut attribute_sources, "manager_key", &mut merged.manager_key, |d| &mut d.manager_key, |v| !v.is_empty());
+        self._survive(&mut records, &mut attribute_sources, "hire_date", &mut merged.hire_date, |d| &mut d.hire_date, |v| v.is_some());
+        self._survive(&mut records, &mut attribute_sources, "termination_date", &mut merged.termination_date, |d| &mut d.termination_date, |v| v.is_some());
+
+        // Free-form attributes, each key is resolved on its own
+        let mut keys: Vec<String> = records.iter().flat_map(|(_, d)| d.attributes.keys().cloned()).collect();
+        keys.sort();
+        keys.dedup();
+        for key in keys {
+            for i in self._precedence_order(&key, &records) {
+                let (source_uid, dto) = &mut records[i];
+                if let Some(value) = dto.attributes.remove(&key).filter(|v| !v.is_empty()) {
+                    attribute_sources.insert(key.clone(), source_uid.clone());
+                    merged.attributes.insert(key, value);
+                    break;
+                }
+            }
+        }
+
+        (merged, attribute_sources)
+    }
+
+    fn _survive<T: Default>(&self, records: &mut [(String, IdentityDTO)], attribute_sources: &mut HashMap<String, String>,
+                            attribute: &str, target: &mut T, field: fn(&mut IdentityDTO) -> &mut T, is_present: fn(&T) -> bool) {
+        for i in self._precedence_order(attribute, records) {
+            let (source_uid, dto) = &mut records[i];
+            let value = field(dto);
+            if is_present(value) {
+                // Moves the value out of the losing DTO, no cloning
+                *target = mem::take(value);
+                attribute_sources.insert(attribute.to_string(), source_uid.clone());
+                return;
+            }
+        }
+    }
+
+    // Indexes of the records, sorted by the precedence of their source for the given attribute.
+    // Sources not listed in the rule keep their configuration order, after the listed ones
+    fn _precedence_order(&self, attribute: &str, records: &[(String, IdentityDTO)]) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..records.len()).collect();
+        if let Some(precedence) = self.attribute_precedence.get(attribute) {
+            order.sort_by_key(|i| precedence.iter().position(|s| *s == records[*i].0).unwrap_or(precedence.len()));
+        }
+        order
+    }
+}
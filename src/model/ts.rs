@@ -1,18 +1,21 @@
 
 use std::collections::HashMap;
-use chrono::{Local, NaiveDate};
+use std::sync::Arc;
+use chrono::{DateTime, FixedOffset, NaiveDate};
 use anyhow::Result;
-use crate::{connectors::{dtos::{AccountDTO, EntitlementDTO}, ad::ADConnector}};
-use super::{iga::{Iga, Identity}, dtos::{EntitlementData, AccountData, CategorizedAccounts, CategoryTotals, CategorizedEntitlements}};
+use crate::{connectors::{dtos::{AccountDTO, AccountFlags, AttributeValue, EntitlementDTO}, traits::TargetSystemConnector, diagnostics::LoadDiagnostic, nesting}};
+use super::{iga::{Iga, Identity}, dn::ObjectIndex, duplicates::{DuplicatePolicy, resolve_duplicates}, dtos::{EntitlementData, AccountData, CategorizedAccounts, CategoryTotals, CategorizedEntitlements}};
 
 
 #[derive(Debug, Clone)]
 pub struct TargetSystemConfig{
     pub unique_id: String,
-    pub connector: ADConnector,                              
+    pub connector: Arc<dyn TargetSystemConnector>,
     pub account_matching_rules: fn(&mut Iga, &mut TargetSystem),
     pub entitlements_ownership_rules: fn(&mut Iga, &mut TargetSystem),
     pub other_attributes: HashMap<String, String>,
+    // Accounts or entitlements loaded more than once with the same unique_id
+    pub duplicate_policy: DuplicatePolicy,
 }
 
 #[derive(Debug)]
@@ -20,6 +23,10 @@ pub struct TargetSystem {
     pub config: TargetSystemConfig,
     pub accounts: HashMap<String, Account>,
     pub entitlements: HashMap<String, Entitlement>,
+    // DNs, sAMAccountNames, GUIDs... of the objects loaded
+    pub index: ObjectIndex,
+    // Problems found in the data by the connector
+    pub diagnostics: Vec<LoadDiagnostic>,
 }
 impl TargetSystem {
     pub fn new(config: TargetSystemConfig) -> Self {
@@ -27,12 +34,27 @@ impl TargetSystem {
             config,
             accounts: HashMap::new(),
             entitlements: HashMap::new(),
+            index: ObjectIndex::default(),
+            diagnostics: Vec::new(),
         }
     }
     pub fn load(&mut self) -> Result<()> {
-        let users_dto = self.config.connector.load_ad_users()?;
-        let entitlements_dto = self.config.connector.load_ad_groups()?;
-    
+        let mut diagnostics = Vec::new();
+        let (users_dto, entitlements_dto) = self.config.connector.load_all_with_diagnostics(&mut diagnostics)?;
+
+        // Objects are keyed by unique_id from here on
+        let policy = self.config.duplicate_policy;
+        let mut users_dto = resolve_duplicates(users_dto, policy, &format!("{} accounts", self.config.unique_id), &mut diagnostics)?;
+        let mut entitlements_dto = resolve_duplicates(entitlements_dto, policy, &format!("{} entitlements", self.config.unique_id), &mut diagnostics)?;
+        self.diagnostics = diagnostics;
+
+        // References between objects are turned into unique_ids, then nesting is calculated again on them
+        self.index = ObjectIndex::from_dtos(&users_dto, &entitlements_dto);
+        self._resolve_references(&mut users_dto, &mut entitlements_dto);
+        self._complete_account_memberof(&mut users_dto, &entitlements_dto);
+        nesting::complete_memberof(&mut entitlements_dto);
+        let entitlements_dto = nesting::calculate_indirect_memberof(entitlements_dto);
+
         self.accounts = users_dto
             .into_iter()
             // Only key for hashmap is cloned. Data object is moved
@@ -47,8 +69,72 @@ impl TargetSystem {
 
         self._populate_account_indirect_access();
 
+        // History provided by the connector itself (if any)
+        for record in self.config.connector.load_account_history()? {
+            if let Some(a) = self.accounts.get_mut(&record.link_key) {
+                a.history.push(record);
+            }
+        }
+        for record in self.config.connector.load_entitlement_history()? {
+            if let Some(e) = self.entitlements.get_mut(&record.link_key) {
+                e.history.push(record);
+            }
+        }
+
         Ok(())
     }
+    // Exports refer to other objects by DN, sAMAccountName or GUID. Unresolved references are kept as they are
+    fn _resolve_references(&self, accounts: &mut [AccountDTO], ents: &mut [EntitlementDTO]) {
+        let index = &self.index;
+        for a in accounts {
+            _resolve_list(&mut a.memberof, |r| index.resolve_entitlement(r));
+            if let Some(manager) = &a.manager {
+                a.manager = Some(index.resolve_account(manager).map(|uid| uid.to_string()).unwrap_or(manager.clone()));
+            }
+        }
+        for e in ents {
+            _resolve_list(&mut e.memberof, |r| index.resolve_entitlement(r));
+            _resolve_list(&mut e.member_groups, |r| index.resolve_entitlement(r));
+            _resolve_list(&mut e.members, |r| index.resolve_uid(r));
+            // Groups found among the members are nested groups
+            let nested: Vec<String> = e.members.iter().flatten()
+                .filter(|m| index.resolve_entitlement(m).is_some())
+                .cloned()
+                .collect();
+            if !nested.is_empty() {
+                let member_groups = e.member_groups.get_or_insert_with(Vec::new);
+                for g in nested {
+                    if !member_groups.contains(&g) {
+                        member_groups.push(g);
+                    }
+                }
+            }
+            _resolve_list(&mut e.ts_owners, |r| index.resolve_uid(r));
+        }
+    }
+    // Some exports only carry the members of the groups, or leave groups out of memberOf (primary group).
+    // Accounts found among the members of a group get the group in their memberof
+    fn _complete_account_memberof(&self, accounts: &mut [AccountDTO], ents: &[EntitlementDTO]) {
+        let mut groups_per_account: HashMap<&str, Vec<&str>> = HashMap::new();
+        for e in ents {
+            for member in e.members.iter().flatten() {
+                // Members were already resolved, so accounts are referred to by their unique_id
+                if self.index.resolve_account(member) == Some(member.as_str()) {
+                    groups_per_account.entry(member).or_default().push(&e.unique_id);
+                }
+            }
+        }
+        for a in accounts {
+            if let Some(groups) = groups_per_account.remove(a.unique_id.as_str()) {
+                let memberof = a.memberof.get_or_insert_with(Vec::new);
+                for g in groups {
+                    if !memberof.iter().any(|m| m == g) {
+                        memberof.push(g.to_string());
+                    }
+                }
+            }
+        }
+    }
     fn _populate_account_indirect_access(&mut self) {
         for acct in self.accounts.values_mut() {
             if let Some(ents) = &acct.memberof {
@@ -79,8 +165,8 @@ impl TargetSystem {
         }
     }
 
-    pub fn get_accounts_data(&self, accts_uids: &Vec<String>) -> Vec<AccountData> {
-        accts_uids.into_iter()
+    pub fn get_accounts_data(&self, accts_uids: &[String]) -> Vec<AccountData> {
+        accts_uids.iter()
                     .filter_map(|uid| self.accounts.get(uid))
                     .map(|acct| acct.to_data(self) )
                     .collect()
@@ -161,9 +247,9 @@ impl TargetSystem {
         }
     }
     
-    pub fn get_entitlements_data(&self, memberships: bool, ents_uids: &Vec<String>) -> Vec<EntitlementData> {
+    pub fn get_entitlements_data(&self, memberships: bool, ents_uids: &[String]) -> Vec<EntitlementData> {
         
-        ents_uids.into_iter()
+        ents_uids.iter()
                     .filter_map(|uid| 
                         self.entitlements.get(uid))
                     .map(|ent| {
@@ -173,26 +259,63 @@ impl TargetSystem {
 
 }
 
+// Attributes ordered by name, as they are printed
+fn sorted_attributes(attributes: &HashMap<String, Option<AttributeValue>>) -> Vec<(String, Option<AttributeValue>)> {
+    let mut sorted: Vec<(String, Option<AttributeValue>)> = attributes.iter()
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect();
+    sorted.sort_by(|a, b| a.0.cmp(&b.0));
+    sorted
+}
+
+// Replaces the references found by their unique_id, dropping the duplicates this can produce
+fn _resolve_list<'a>(list: &mut Option<Vec<String>>, resolve: impl Fn(&str) -> Option<&'a str>) {
+    if let Some(references) = list {
+        let mut resolved: Vec<String> = Vec::with_capacity(references.len());
+        for r in references.drain(..) {
+            let uid = resolve(&r).map(|uid| uid.to_string()).unwrap_or(r);
+            if !resolved.contains(&uid) {
+                resolved.push(uid);
+            }
+        }
+        *references = resolved;
+    }
+}
+
+// Object that lives in another target system
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForeignObject {
+    pub ts_uid: String,
+    pub unique_id: String,
+}
+
 #[derive(Debug)]
 pub struct Account {
     pub unique_id: String,
+    pub sids: Vec<String>,
     pub display_name: Option<String>,
     pub description: Option<String>,
     
-    pub created: Option<NaiveDate>,
-    pub last_logon: Option<NaiveDate>,
-    pub password_last_set: Option<NaiveDate>,
-    pub expiration_date: Option<NaiveDate>,
+    pub created: Option<DateTime<FixedOffset>>,
+    pub last_logon: Option<DateTime<FixedOffset>>,
+    pub password_last_set: Option<DateTime<FixedOffset>>,
+    pub expiration_date: Option<DateTime<FixedOffset>>,
 
     pub enabled: Option<bool>,
     pub deleted: Option<bool>,
     pub locked: Option<bool>,
+    pub flags: Option<AccountFlags>,
 
     pub memberof: Option<Vec<String>>,
     pub memberof_indirect: Vec<String>,
+    // Groups of other target systems (trusted domains) the account is a member of as foreign security principal
+    pub foreign_memberof: Vec<ForeignObject>,
+    // Groups of other target systems reached through nesting, or through the account's own groups
+    pub foreign_memberof_indirect: Vec<ForeignObject>,
     pub ou: Option<String>,
+    pub manager: Option<String>,
 
-    pub other_attributes: HashMap<String, Option<String>>, 
+    pub other_attributes: HashMap<String, Option<AttributeValue>>, 
     pub account_type: String,
 
     pub syncs_from_ts: Option<String>,
@@ -207,10 +330,10 @@ impl Account {
     pub fn from_dto(dto: AccountDTO) -> Self {
 
         let mut history = Vec::new();
-        if let Some(created) = dto.created.clone() {
+        if let Some(created) = dto.created {
             history.push(HistoryRecord { 
                 link_key: "".to_string(), 
-                date: created, 
+                date: created.date_naive(), 
                 source: "Account data".to_string(), 
                 event_name: "Account creation".to_string(), 
                 initiator: "".to_string(), 
@@ -220,10 +343,11 @@ impl Account {
         
         Self {
             unique_id: dto.unique_id,
+            sids: dto.sids,
             display_name: dto.display_name,
             description: dto.description,
     
-            created: dto.created.clone(),
+            created: dto.created,
             last_logon: dto.last_logon,
             password_last_set: dto.password_last_set,
             expiration_date: dto.expiration_date,
@@ -231,10 +355,14 @@ impl Account {
             enabled: dto.enabled,
             deleted: dto.deleted,
             locked: dto.locked,
+            flags: dto.flags,
 
             memberof: dto.memberof,
             memberof_indirect: Vec::new(),
+            foreign_memberof: Vec::new(),
+            foreign_memberof_indirect: Vec::new(),
             ou: dto.ou,
+            manager: dto.manager,
             other_attributes: dto.other_attributes, 
             account_type: "".to_string(),
             
@@ -257,11 +385,13 @@ impl Account {
             account_type: self.account_type.clone(),
             ou: self.ou.clone().unwrap_or("".to_string()),
             enabled: self.enabled.map(|e| if e { "Yes ".to_string()} else { "No ".to_string()}).unwrap_or("N/A".to_string()),
+            flags: self.flags.as_ref().map(|f| f.names().join(", ")).unwrap_or("".to_string()),
             syncs_to_ts: self.syncs_to_ts.clone().unwrap_or("".to_string()),
             syncs_to_account: self.syncs_to_account.clone().unwrap_or("".to_string()),
             entitlements: self._get_entitlements_data(ts),
             indirect_entitlements: self._get_indirect_entitlements_data(ts),
             identity_owners: self.identity_owners.clone(),
+            other_attributes: sorted_attributes(&self.other_attributes),
             macheo: false,
             history: self.history.clone(),
         }
@@ -275,7 +405,7 @@ impl Account {
         }
     }
     fn _get_indirect_entitlements_data(&self, ts: &TargetSystem) -> Vec<EntitlementData> {
-        if self.memberof_indirect.len() > 0 {
+        if !self.memberof_indirect.is_empty() {
             ts.get_entitlements_data(false, &self.memberof_indirect)
         } else {
             Vec::new()
@@ -286,17 +416,15 @@ impl Account {
         self.memberof.as_ref().map(|memberof| memberof.len()).unwrap_or(0)
     }
     pub fn is_orphan(&self) -> bool {
-        self.enabled == Some(true) && self.identity_owners.len() == 0
+        self.enabled == Some(true) && self.identity_owners.is_empty()
     }
     pub fn is_persistent_leaver(&self, idents: &HashMap<String, Identity>) -> bool {
-        if self.identity_owners.len() == 0 {
+        if self.identity_owners.is_empty() {
             return false
         }
         self.enabled == Some(true) &&
         self.identity_owners.iter().all(|ident_uid| 
-            idents.get(ident_uid).map_or(false, 
-                |ident| ident.is_inactive() && ident.termination_date.map(|d| d < Local::now().naive_local().into() ).unwrap_or(false)
-            ))
+            idents.get(ident_uid).is_some_and(|ident| ident.is_leaver()))
     }
 
 }
@@ -304,16 +432,21 @@ impl Account {
 #[derive(Debug)] 
 pub struct Entitlement {
     pub unique_id: String,
+    pub sids: Vec<String>,
     pub display_name: Option<String>,
     pub description: Option<String>,
-    pub created: Option<NaiveDate>,
+    pub created: Option<DateTime<FixedOffset>>,
 
     pub memberof: Option<Vec<String>>,
     pub all_indirect_memberof: Vec<String>,
     pub members: Option<Vec<String>>,
+    // Accounts and groups of other target systems that are members of the group
+    pub foreign_members: Vec<ForeignObject>,
+    // Groups of other target systems the group is a member of
+    pub foreign_memberof: Vec<ForeignObject>,
     pub ou: Option<String>,
 
-    pub other_attributes: HashMap<String, Option<String>>, 
+    pub other_attributes: HashMap<String, Option<AttributeValue>>, 
     pub entitlement_type: String,
 
     pub syncs_from_ts: Option<String>,
@@ -330,10 +463,10 @@ impl Entitlement {
     pub fn from_dto(dto: EntitlementDTO) -> Self {
 
         let mut history = Vec::new();
-        if let Some(created) = dto.created.clone() {
+        if let Some(created) = dto.created {
             history.push(HistoryRecord { 
                 link_key: "".to_string(), 
-                date: created, 
+                date: created.date_naive(), 
                 source: "Group data".to_string(), 
                 event_name: "Group creation".to_string(), 
                 initiator: "".to_string(), 
@@ -343,12 +476,15 @@ impl Entitlement {
 
         Self {
             unique_id: dto.unique_id,
+            sids: dto.sids,
             display_name: dto.display_name,
             description: dto.description,
             created: dto.created,
             memberof: dto.memberof,
             all_indirect_memberof: dto.all_indirect_memberof,
             members: dto.members,
+            foreign_members: Vec::new(),
+            foreign_memberof: Vec::new(),
             ou: dto.ou,
             other_attributes: dto.other_attributes,
             entitlement_type: "".to_string(),
@@ -358,7 +494,7 @@ impl Entitlement {
             syncs_to_ts: None,
             syncs_to_entitlement: None,
             identity_owners: Vec::new(),
-            ts_owners: None,
+            ts_owners: dto.ts_owners,
             history,
         }
     }
@@ -382,6 +518,7 @@ impl Entitlement {
             syncs_to_ts: self.syncs_to_ts.clone().unwrap_or("".to_string()),
             syncs_to_entitlement: self.syncs_to_entitlement.clone().unwrap_or("".to_string()),
             identity_owners: self.identity_owners.clone(),
+            other_attributes: sorted_attributes(&self.other_attributes),
             macheo: false,
             history: self.history.clone(),
         }
@@ -389,15 +526,9 @@ impl Entitlement {
     
     fn _get_member_accounts_data(&self, ts: &TargetSystem) -> Vec<AccountData> {
 
+        // Members were resolved to unique_ids when loading
         if let Some(member_uids) = &self.members {
-            let sams: Vec<String> = member_uids.clone().into_iter().map(|dn|
-                dn.split(',')
-                .next()
-                .filter(|part| part.starts_with("CN="))
-                .map(|part| part.trim_start_matches("CN="))
-                .unwrap_or(&dn).to_string()
-            ).collect();
-            ts.get_accounts_data(&sams)
+            ts.get_accounts_data(member_uids)
         } else {
             Vec::new()
         }
@@ -2,9 +2,10 @@ use std::{collections::HashMap};
 use anyhow::{Result, anyhow};
 
 use rust_xlsxwriter::{Workbook, Format, XlsxError, FormatAlign, Color};
-use crate::model::{iga::Iga, dtos::{AccountData, EntitlementData, CategoryTotals, IdentityData}};
+use crate::model::{iga::Iga, dtos::{AccountData, EntitlementData, CategoryTotals, IdentityData, IdentityChangeData}};
+use crate::connectors::diagnostics::LoadDiagnostic;
 
-use super::sheets::{Sheet, TotalsSheet, AccountSet, EntitlementSet, IdentitySummaryPrinter, SyncedAccountSet, SyncedEntitlementSet};
+use super::sheets::{Sheet, TotalsSheet, AccountSet, EntitlementSet, IdentitySummaryPrinter, SyncedAccountSet, SyncedEntitlementSet, DataQualitySheet, IdentityChangesSheet, AccessStillHeldSheet};
 
 
 pub struct ExcelReportGenerator<'a> {
@@ -34,10 +35,10 @@ impl<'a> ExcelReportGenerator<'a>{
             ef.add_sheet("Owned groups (history)", 
                 SheetType::EntitlementListHistory{entitlements: ident_data.get_owned_groups_ref()})?;
             
-            ef.save(&ident_data.unique_id)?;
+            self._save(&mut ef, &ident_data.unique_id)?;
             Ok(())
         } else {
-            return Err(anyhow!("Identity not found for UID: {}", ident_uid));
+            Err(anyhow!("Identity not found for UID: {}", ident_uid))
         }
         
     }
@@ -46,7 +47,7 @@ impl<'a> ExcelReportGenerator<'a>{
         for category_totals in self.iga.get_entitlement_count_per_type() {
             ef.add_sheet(&category_totals.ts_uid, SheetType::Totals{totals: &category_totals})?;
         }
-        ef.save("Entitlement type totals")?;
+        self._save(&mut ef, "Entitlement type totals")?;
         Ok(())
     }
     pub fn cr_entitlement_type_lists(&self) -> Result<()> {
@@ -57,7 +58,7 @@ impl<'a> ExcelReportGenerator<'a>{
                 data.insert(category.clone(), v.iter().collect());
                 ef.add_sheet(&category, SheetType::EntitlementList{data})?;
             }
-            ef.save(&format!("Entitlement categorization - {}",&categorized_ents.ts_uid))?;
+            self._save(&mut ef, &format!("Entitlement categorization - {}",&categorized_ents.ts_uid))?;
         }
         Ok(())
     }
@@ -67,7 +68,7 @@ impl<'a> ExcelReportGenerator<'a>{
         for category_totals in self.iga.get_entitlement_count_per_ou() {
             ef.add_sheet(&category_totals.ts_uid, SheetType::Totals{totals: &category_totals})?;
         }
-        ef.save("Entitlements cout per ou")?;
+        self._save(&mut ef, "Entitlements cout per ou")?;
         Ok(())
     }
     pub fn cr_account_type_totals(&self) -> Result<()> {
@@ -75,7 +76,7 @@ impl<'a> ExcelReportGenerator<'a>{
         for category_totals in self.iga.get_account_count_per_type() {
             ef.add_sheet(&category_totals.ts_uid, SheetType::Totals{totals: &category_totals})?;
         }
-        ef.save("Accounts type totals")?;
+        self._save(&mut ef, "Accounts type totals")?;
         Ok(())
     }
     pub fn cr_account_type_lists(&self) -> Result<()> {
@@ -86,7 +87,7 @@ impl<'a> ExcelReportGenerator<'a>{
                 data.insert(category.clone(), v.iter().collect());
                 ef.add_sheet(&category, SheetType::AccountList{data})?;
             }
-            ef.save(&format!("Account categorization - {}",&categorized_accts.ts_uid))?;
+            self._save(&mut ef, &format!("Account categorization - {}",&categorized_accts.ts_uid))?;
         }
         Ok(())
     }
@@ -100,7 +101,14 @@ impl<'a> ExcelReportGenerator<'a>{
             data.insert(ts_uid.clone(), v.iter().collect());
             ef.add_sheet(ts_uid.as_str(), SheetType::AccountList{data})?;
         }
-        ef.save("All Orphan Accounts")?;
+        self._save(&mut ef, "All Orphan Accounts")?;
+        Ok(())
+    }
+
+    pub fn cr_data_quality(&self) -> Result<()> {
+        let mut ef = ExcelFileBuilder::new();
+        ef.add_sheet("Data quality", SheetType::DataQuality{diagnostics: &self.iga.diagnostics})?;
+        ef.save("Data quality")?;
         Ok(())
     }
 
@@ -112,10 +120,27 @@ impl<'a> ExcelReportGenerator<'a>{
             data.insert(ts_uid.clone(), v.iter().collect());
             ef.add_sheet(&ts_uid, SheetType::AccountList{data})?;
         }
-        ef.save("Persistent accounts")?;
+        self._save(&mut ef, "Persistent accounts")?;
+        Ok(())
+    }
+
+    // Needs a previous identity snapshot in the configuration
+    pub fn cr_identity_changes(&self) -> Result<()> {
+        let mut ef = ExcelFileBuilder::new();
+        let changes = self.iga.get_identity_changes_data();
+        ef.add_sheet("Joiners, movers, leavers", SheetType::IdentityChanges{changes: &changes})?;
+        ef.add_sheet("Access still held", SheetType::AccessStillHeld{changes: &changes})?;
+        self._save(&mut ef, "Joiners, movers and leavers")?;
         Ok(())
     }
 
+    // Reports based on data with problems (lenient load) say so in an additional sheet
+    fn _save(&self, ef: &mut ExcelFileBuilder, filename: &str) -> Result<(), XlsxError> {
+        if !self.iga.diagnostics.is_empty() {
+            ef.add_sheet("Data quality", SheetType::DataQuality{diagnostics: &self.iga.diagnostics})?;
+        }
+        ef.save(filename)
+    }
 }
 enum SheetType<'a> {
     Totals {totals: &'a CategoryTotals},
@@ -129,7 +154,10 @@ enum SheetType<'a> {
     
     AccountsListHistory {accounts: HashMap<String, Vec<&'a AccountData>>},
     EntitlementListHistory {entitlements: HashMap<String, Vec<&'a EntitlementData>>},
-    
+
+    DataQuality {diagnostics: &'a [LoadDiagnostic]},
+    IdentityChanges {changes: &'a [IdentityChangeData]},
+    AccessStillHeld {changes: &'a [IdentityChangeData]},
 }
 
 pub struct ExcelReportFormat {
@@ -193,6 +221,15 @@ impl ExcelFileBuilder {
 
             SheetType::EntitlementListHistory {entitlements: ents} => 
                 EntitlementSet(ents).print_with_history(&mut sheet)?, 
+
+            SheetType::DataQuality {diagnostics} =>
+                DataQualitySheet(diagnostics).print(&mut sheet)?,
+
+            SheetType::IdentityChanges {changes} =>
+                IdentityChangesSheet(changes).print(&mut sheet)?,
+
+            SheetType::AccessStillHeld {changes} =>
+                AccessStillHeldSheet(changes).print(&mut sheet)?,
         }
         Ok(())
     }
@@ -1,8 +1,10 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use rust_xlsxwriter::XlsxError;
 use rust_xlsxwriter::{Worksheet, Format};
 use crate::model::ts::HistoryRecord;
-use crate::model::dtos::{CategoryTotals, EntitlementData, AccountData, IdentityData};
+use crate::model::dtos::{CategoryTotals, EntitlementData, AccountData, IdentityData, IdentityChangeData};
+use crate::connectors::dtos::AttributeValue;
+use crate::connectors::diagnostics::LoadDiagnostic;
 use super::reports_xlsx::ExcelReportFormat;
 
 
@@ -12,6 +14,36 @@ pub struct Sheet<'a> {
     pub format: &'a ExcelReportFormat,
 }
 
+// Other attributes are printed after the fixed columns, one column per attribute
+const ACCOUNT_COLUMNS: u16 = 8;
+const ENTITLEMENT_COLUMNS: u16 = 7;
+
+fn other_attribute_names<'a>(attribute_lists: impl Iterator<Item = &'a Vec<(String, Option<AttributeValue>)>>) -> Vec<&'a str> {
+    let names: BTreeSet<&str> = attribute_lists.flatten().map(|(k, _)| k.as_str()).collect();
+    names.into_iter().collect()
+}
+fn print_other_attributes_header(i: u32, sheet: &mut Sheet, first_column: u16, names: &[&str]) -> Result<(), XlsxError> {
+    for (column, name) in (first_column..).zip(names) {
+        sheet.worksheet.write_with_format(i, column, *name, &sheet.format.header)?;
+        sheet.worksheet.set_column_width(column, 20)?;
+    }
+    Ok(())
+}
+fn print_other_attributes(i: u32, sheet: &mut Sheet, first_column: u16, names: &[&str], attributes: &[(String, Option<AttributeValue>)]) -> Result<(), XlsxError> {
+    for (column, name) in (first_column..).zip(names) {
+        let value = attributes.iter().find(|(k, _)| k == name).and_then(|(_, v)| v.as_ref());
+        // Numbers and booleans keep their type in the cell, multi-valued attributes are joined
+        match value {
+            Some(AttributeValue::Integer(n)) => { sheet.worksheet.write(i, column, *n as f64)?; }
+            Some(AttributeValue::Float(n)) => { sheet.worksheet.write(i, column, *n)?; }
+            Some(AttributeValue::Bool(b)) => { sheet.worksheet.write(i, column, *b)?; }
+            Some(v) => { sheet.worksheet.write(i, column, v.to_string())?; }
+            None => {}
+        }
+    }
+    Ok(())
+}
+
 trait XlsxPrint {
     fn print_header(&self, i: u32, sheet: &mut Sheet, ts_string: &str) -> Result<u32, XlsxError>;
     fn print(&self, i: u32, sheet: &mut Sheet, ts_string: &str) -> Result<u32, XlsxError>;
@@ -33,6 +65,8 @@ impl XlsxPrint for AccountData {
         sheet.worksheet.write_with_format(i, 5, "Identity owner(s)", &sheet.format.header)?;
         sheet.worksheet.set_column_width(5, 15)?;
         sheet.worksheet.write_with_format(i, 6, "Description", &sheet.format.header)?;
+        sheet.worksheet.set_column_width(6, 25)?;
+        sheet.worksheet.write_with_format(i, 7, "Flags", &sheet.format.header)?;
         Ok(i+1)
     }
     fn print(&self, i: u32, sheet: &mut Sheet, ts_string: &str) -> Result<u32, XlsxError> {
@@ -40,8 +74,9 @@ impl XlsxPrint for AccountData {
         sheet.worksheet.write(i, 2, &self.account_type)?;
         sheet.worksheet.write(i, 3, &self.ou)?;
         sheet.worksheet.write(i, 4, &self.enabled)?; 
-        sheet.worksheet.write(i, 5, &self.identity_owners.join(", "))?;
+        sheet.worksheet.write(i, 5, self.identity_owners.join(", "))?;
         sheet.worksheet.write(i, 6, &self.description)?;
+        sheet.worksheet.write(i, 7, &self.flags)?;
         Ok(i+1)
     }
 
@@ -49,10 +84,10 @@ impl XlsxPrint for AccountData {
         
         // To avoid cloning, create a Vec of references, and sort the references
         let mut record_refs: Vec<&HistoryRecord> = self.history.iter().collect();
-        record_refs.sort_by(|a, b| b.date.cmp(&a.date));
+        record_refs.sort_by_key(|r| std::cmp::Reverse(r.date));
 
         // Print header
-        if record_refs.len() > 0 {
+        if !record_refs.is_empty() {
             sheet.worksheet.write_with_format(i, 1, "History date", &sheet.format.header_secondary)?;
             sheet.worksheet.write_with_format(i, 2, "Event name", &sheet.format.header_secondary)?;
             sheet.worksheet.write_with_format(i, 3, "Source", &sheet.format.header_secondary)?;
@@ -65,7 +100,7 @@ impl XlsxPrint for AccountData {
 
         // Print values
         for record in record_refs {
-            sheet.worksheet.write_with_format(i, 1, &record.date.to_string(), &sheet.format.standard)?;
+            sheet.worksheet.write_with_format(i, 1, record.date.to_string(), &sheet.format.standard)?;
             sheet.worksheet.write_with_format(i, 2, &record.event_name, &sheet.format.standard)?;
             sheet.worksheet.write_with_format(i, 3, &record.source, &sheet.format.standard)?;
             sheet.worksheet.write_with_format(i, 4, &record.state, &sheet.format.standard)?;
@@ -97,7 +132,7 @@ impl XlsxPrint for EntitlementData {
         sheet.worksheet.write(i, 1, format!("{}\\{}", ts_string, &self.display_name))?;
         sheet.worksheet.write(i, 2, &self.entitlement_type)?;
         sheet.worksheet.write(i, 3, &self.ou)?;
-        sheet.worksheet.write(i, 4, &self.identity_owners.join(", "))?;
+        sheet.worksheet.write(i, 4, self.identity_owners.join(", "))?;
         sheet.worksheet.write(i, 6, &self.description)?;
         Ok(i+1)
     }
@@ -106,10 +141,10 @@ impl XlsxPrint for EntitlementData {
         
          // To avoid cloning, create a Vec of references, and sort the references
         let mut record_refs: Vec<&HistoryRecord> = self.history.iter().collect();
-        record_refs.sort_by(|a, b| b.date.cmp(&a.date));
+        record_refs.sort_by_key(|r| std::cmp::Reverse(r.date));
 
         // Print header
-        if record_refs.len() > 0 {
+        if !record_refs.is_empty() {
             sheet.worksheet.write_with_format(i, 1, "History date", &sheet.format.header_secondary)?;
             sheet.worksheet.write_with_format(i, 2, "Event name", &sheet.format.header_secondary)?;
             sheet.worksheet.write_with_format(i, 3, "Source", &sheet.format.header_secondary)?;
@@ -122,7 +157,7 @@ impl XlsxPrint for EntitlementData {
 
         // Print values
         for record in record_refs {
-            sheet.worksheet.write_with_format(i, 1, &record.date.to_string(), &sheet.format.standard)?;
+            sheet.worksheet.write_with_format(i, 1, record.date.to_string(), &sheet.format.standard)?;
             sheet.worksheet.write_with_format(i, 2, &record.event_name, &sheet.format.standard)?;
             sheet.worksheet.write_with_format(i, 3, &record.source, &sheet.format.standard)?;
             sheet.worksheet.write_with_format(i, 4, &record.state, &sheet.format.standard)?;
@@ -190,14 +225,14 @@ impl SyncedAccounts<'_> {
     }
     fn print_memberships(&self, mut i: u32, sheet: &mut Sheet) -> Result<u32, XlsxError> {
         
-        if self.from.entitlements.len() == 0 {
+        if self.from.entitlements.is_empty() {
             sheet.worksheet.write(i, 1, "(No memberships)")?;
             i += 1;
         } else {
 
             // Sort memberships to print (to avoid cloning, the references are copied and serted).
             let mut sorted_refs: Vec<&EntitlementData> = self.from.entitlements.iter().collect(); 
-            sorted_refs.sort_by(|a, b| a.display_name.to_lowercase().cmp(&b.display_name.to_lowercase()));
+            sorted_refs.sort_by_key(|a| a.display_name.to_lowercase());
 
             for membership_from in sorted_refs {
                 let found = if let Some (to) = &self.to {
@@ -208,7 +243,7 @@ impl SyncedAccounts<'_> {
 
             // Sort memberships to print (to avoid cloning, the references are copied and serted).
             let mut sorted_refs: Vec<&EntitlementData> = self.from.indirect_entitlements.iter().collect(); 
-            sorted_refs.sort_by(|a, b| a.display_name.to_lowercase().cmp(&b.display_name.to_lowercase()));
+            sorted_refs.sort_by_key(|a| a.display_name.to_lowercase());
 
             for membership_from in sorted_refs {
                 let found = if let Some (to) = &self.to {
@@ -220,7 +255,7 @@ impl SyncedAccounts<'_> {
 
         if let Some (acct_to) = &self.to    {
             for memberships_to_extra in &acct_to.entitlements {
-                if memberships_to_extra.macheo == false {
+                if !memberships_to_extra.macheo {
                     i = self.print_membership(i, sheet.worksheet, &None, "Direct", &Some(memberships_to_extra), &sheet.format.standard)?;
                 }
                 
@@ -228,7 +263,7 @@ impl SyncedAccounts<'_> {
         }
         if let Some (acct_to) = &self.to    {
             for memberships_to_extra in &acct_to.indirect_entitlements {
-                if memberships_to_extra.macheo == false {
+                if !memberships_to_extra.macheo {
                     i = self.print_membership(i, sheet.worksheet, &None, "Indirect", &Some(memberships_to_extra), &sheet.format.grayout_format)?;
                 }
                 
@@ -272,7 +307,7 @@ impl SyncedEntitlements<'_> {
     fn print_header(&self, i: u32, sheet: &mut Sheet, ts_string: &str, sync_header: bool) -> Result<u32, XlsxError> {
         
         sheet.worksheet.write_with_format(i, 0, ts_string, &sheet.format.header)?;
-        sheet.worksheet.write_with_format(i, 1, format!("Group"), &sheet.format.header)?;
+        sheet.worksheet.write_with_format(i, 1, "Group".to_string(), &sheet.format.header)?;
         sheet.worksheet.set_column_width(1, 40)?;
         sheet.worksheet.write_with_format(i, 2, "Type", &sheet.format.header)?;
         sheet.worksheet.set_column_width(2, 20)?;
@@ -321,14 +356,14 @@ impl SyncedEntitlements<'_> {
         Ok(i+1)
     }
     fn print_memberships(&self, mut i: u32, sheet: &mut Sheet) -> Result<u32, XlsxError> {
-        if self.from.member_accounts.len() == 0 {
+        if self.from.member_accounts.is_empty() {
             sheet.worksheet.write(i, 1, "(No member users)")?;
             i += 1;
         } else {
             
             // Sort member accounts to print (to avoid cloning, the references are copied and serted).
             let mut sorted_refs: Vec<&AccountData> = self.from.member_accounts.iter().collect(); 
-            sorted_refs.sort_by(|a, b| a.display_name.to_lowercase().cmp(&b.display_name.to_lowercase()));
+            sorted_refs.sort_by_key(|a| a.display_name.to_lowercase());
 
             for membership_from in sorted_refs {
                 let found = if let Some (to) = &self.to {
@@ -359,13 +394,13 @@ impl SyncedEntitlements<'_> {
         Ok(i+1)
     }
     fn print_group_memberships(&self, mut i: u32, sheet: &mut Sheet) -> Result<u32, XlsxError> {
-        if self.from.member_groups.len() == 0 {
+        if self.from.member_groups.is_empty() {
             sheet.worksheet.write(i, 1, "(No group members)")?;
         } else {
             
             // Sort member groups to print (to avoid cloning, the references are copied and serted).
             let mut sorted_refs: Vec<&EntitlementData> = self.from.member_groups.iter().collect(); 
-            sorted_refs.sort_by(|a, b| a.display_name.to_lowercase().cmp(&b.display_name.to_lowercase()));
+            sorted_refs.sort_by_key(|a| a.display_name.to_lowercase());
 
             for membership_from in sorted_refs {
                 let found = if let Some (to) = &self.to { 
@@ -430,6 +465,7 @@ impl SyncedEntitlements<'_> {
 }
 
 
+#[derive(Default)]
 pub struct SyncedAccountSet<'a> {
     sync: Option<(&'a str, &'a str)>,
     synced_from_to: HashMap<String, Vec<SyncedAccounts<'a>>>,
@@ -549,7 +585,7 @@ impl<'a> SyncedAccountSet<'a> {
         
             // Sort accounts to print (to avoid cloning, the references are copied and serted).
             let mut sorted_refs: Vec<&SyncedAccounts> = synced_from_to.iter().collect(); 
-            sorted_refs.sort_by(|a, b| a.from.display_name.to_lowercase().cmp(&b.from.display_name.to_lowercase()));
+            sorted_refs.sort_by_key(|a| a.from.display_name.to_lowercase());
 
             // Print accounts' data
             for synced_acct in sorted_refs {
@@ -562,7 +598,7 @@ impl<'a> SyncedAccountSet<'a> {
 
         // Sort accounts to print (to avoid cloning, the references are copied and sorted).
         let mut sorted_refs: Vec<&SyncedAccounts> = synced_from_to.iter().collect(); 
-        sorted_refs.sort_by(|a, b| a.from.display_name.to_lowercase().cmp(&b.from.display_name.to_lowercase()));
+        sorted_refs.sort_by_key(|a| a.from.display_name.to_lowercase());
 
         // Print accounts' headers and data
         for synced_acct in sorted_refs {
@@ -577,16 +613,8 @@ impl<'a> SyncedAccountSet<'a> {
         Ok(i)
     }
 }
-impl Default for SyncedAccountSet<'_> {
-    fn default() -> Self {
-        Self { 
-            sync: Default::default(), 
-            synced_from_to: Default::default(), 
-            nosync_to: Default::default(), 
-        }
-    }
-}
 
+#[derive(Default)]
 pub struct SyncedEntitlementSet<'a> {
     sync: Option<(&'a str, &'a str)>,
     synced_from_to: HashMap<String, Vec<SyncedEntitlements<'a>>>,
@@ -695,7 +723,7 @@ impl<'a> SyncedEntitlementSet<'a> {
                 
         // Sort entitlements to print (to avoid cloning, the references are copied and serted).
         let mut sorted_refs: Vec<&SyncedEntitlements> = synced_from_to.iter().collect(); 
-        sorted_refs.sort_by(|a, b| a.from.display_name.to_lowercase().cmp(&b.from.display_name.to_lowercase()));
+        sorted_refs.sort_by_key(|a| a.from.display_name.to_lowercase());
 
         // Print entitlements
         for synced_ent in sorted_refs {
@@ -709,7 +737,7 @@ impl<'a> SyncedEntitlementSet<'a> {
 
         // Sort entitlements to print (to avoid cloning, the references are copied and serted).
         let mut sorted_refs: Vec<&SyncedEntitlements> = synced_from_to.iter().collect(); 
-        sorted_refs.sort_by(|a, b| a.from.display_name.to_lowercase().cmp(&b.from.display_name.to_lowercase()));
+        sorted_refs.sort_by_key(|a| a.from.display_name.to_lowercase());
 
         for synced_ent in sorted_refs {
             i = synced_ent.print_header(i, sheet, sync_from_ts_uid, sync_header)?;
@@ -726,14 +754,6 @@ impl<'a> SyncedEntitlementSet<'a> {
         Ok(i)
     }
 }
-impl Default for SyncedEntitlementSet<'_> {
-    fn default() -> Self {
-        Self { 
-            sync: Default::default(), 
-            synced_from_to: Default::default(), 
-            nosync_to: Default::default(),  }
-    }
-}
 
 pub struct IdentitySummaryPrinter<'a> {
     identity: &'a mut IdentityData,
@@ -770,17 +790,20 @@ impl AccountSet<'_> {
 
         for (ts, accts) in &self.0 {
             
-            if self.0.len() > 0 {
+            if !self.0.is_empty() {
                 // Print header
+                let attribute_names = other_attribute_names(accts.iter().map(|a| &a.other_attributes));
+                print_other_attributes_header(i, sheet, ACCOUNT_COLUMNS, &attribute_names)?;
                 i = accts[0].print_header(i, sheet, ts)?;
 
                 // Sorting accounts to print (to avoid cloning, the references are copied and sorted).
                 let mut sorted_refs = accts.clone(); //Cloning references
-                sorted_refs.sort_by(|a, b| a.display_name.to_lowercase().cmp(&b.display_name.to_lowercase()));
+                sorted_refs.sort_by_key(|a| a.display_name.to_lowercase());
 
                 // Print accounts' data
                 for acct in sorted_refs {
-                    i = acct.print(i, sheet, &ts)?;
+                    print_other_attributes(i, sheet, ACCOUNT_COLUMNS, &attribute_names, &acct.other_attributes)?;
+                    i = acct.print(i, sheet, ts)?;
                 }
             }
 
@@ -797,12 +820,15 @@ impl AccountSet<'_> {
 
             // Sorting accounts to print (to avoid cloning, the references are copied and sorted).
             let mut sorted_refs = accts.clone(); //Cloning references
-            sorted_refs.sort_by(|a, b| a.display_name.to_lowercase().cmp(&b.display_name.to_lowercase()));
+            sorted_refs.sort_by_key(|a| a.display_name.to_lowercase());
 
             // Print headers and accounts
             for acct in sorted_refs {
+                let attribute_names = other_attribute_names(std::iter::once(&acct.other_attributes));
+                print_other_attributes_header(i, sheet, ACCOUNT_COLUMNS, &attribute_names)?;
                 i = acct.print_header(i, sheet, ts)?;
-                i = acct.print(i, sheet, &ts)?;
+                print_other_attributes(i, sheet, ACCOUNT_COLUMNS, &attribute_names, &acct.other_attributes)?;
+                i = acct.print(i, sheet, ts)?;
     
                 i = acct.print_histories(i, sheet)?;
                 i += 1;
@@ -820,16 +846,19 @@ impl EntitlementSet<'_> {
 
         for (ts, ents) in &self.0 {
             
-            if ents.len() > 0 {
+            if !ents.is_empty() {
                 // Print header
+                let attribute_names = other_attribute_names(ents.iter().map(|e| &e.other_attributes));
+                print_other_attributes_header(i, sheet, ENTITLEMENT_COLUMNS, &attribute_names)?;
                 i = ents[0].print_header(i, sheet, ts)?;
 
                 // Sorting entitlements to print (to avoid cloning, the references are copied and sorted).
                 let mut sorted_refs = ents.clone(); //Cloning references
-                sorted_refs.sort_by(|a, b| a.display_name.to_lowercase().cmp(&b.display_name.to_lowercase()));
+                sorted_refs.sort_by_key(|a| a.display_name.to_lowercase());
                 
                 // Print entitlements' data
                 for ent in sorted_refs {
+                    print_other_attributes(i, sheet, ENTITLEMENT_COLUMNS, &attribute_names, &ent.other_attributes)?;
                     i = ent.print(i, sheet, ts)?;
                 }
             }
@@ -847,11 +876,14 @@ impl EntitlementSet<'_> {
 
             // Sorting entitlements to print (to avoid cloning, the references are copied and sorted).
             let mut sorted_refs = ents.clone(); //Cloning references
-            sorted_refs.sort_by(|a, b| a.display_name.to_lowercase().cmp(&b.display_name.to_lowercase()));
+            sorted_refs.sort_by_key(|a| a.display_name.to_lowercase());
         
             // Print headers and entitlements
             for ent in sorted_refs {
+                let attribute_names = other_attribute_names(std::iter::once(&ent.other_attributes));
+                print_other_attributes_header(i, sheet, ENTITLEMENT_COLUMNS, &attribute_names)?;
                 i = ent.print_header(i, sheet, ts)?;
+                print_other_attributes(i, sheet, ENTITLEMENT_COLUMNS, &attribute_names, &ent.other_attributes)?;
                 i = ent.print(i, sheet, ts)?;
     
                 i = ent.print_histories(i, sheet)?;
@@ -869,13 +901,105 @@ impl<'a> TotalsSheet<'a> {
         sheet.worksheet.write_with_format(0, 0, &self.0.categories, &sheet.format.header)?;
         sheet.worksheet.set_column_width(0, 25)?;
         sheet.worksheet.write_with_format(0, 1, "Amount", &sheet.format.header)?;
-        let mut i = 1;
-        for total in &self.0.totals {
+        for (i, total) in (1..).zip(self.0.totals.iter()) {
             sheet.worksheet.write(i, 0, total.0)?; 
             sheet.worksheet.write(i, 1, *total.1)?;
-            i += 1;
         }
         Ok(())
     }
 }
 
+// Problems found while loading the data the report is based on
+pub struct DataQualitySheet<'a>(pub &'a [LoadDiagnostic]);
+impl DataQualitySheet<'_> {
+    pub fn print(&mut self, sheet: &mut Sheet) -> Result<(), XlsxError>  {
+        print_sheet_header(sheet, &[("Severity", 10.0), ("Source", 40.0), ("Object", 8.0), ("Attribute", 25.0), ("Problem", 80.0)])?;
+        for (i, d) in (1..).zip(self.0.iter()) {
+            sheet.worksheet.write(i, 0, format!("{:?}", d.severity))?;
+            sheet.worksheet.write(i, 1, &d.source)?;
+            if let Some(index) = d.object_index {
+                sheet.worksheet.write(i, 2, index as u32)?;
+            }
+            if let Some(attribute) = &d.attribute {
+                sheet.worksheet.write(i, 3, attribute)?;
+            }
+            sheet.worksheet.write(i, 4, &d.problem)?;
+        }
+        Ok(())
+    }
+}
+
+fn print_sheet_header(sheet: &mut Sheet, columns: &[(&str, f64)]) -> Result<(), XlsxError> {
+    for (j, (title, width)) in columns.iter().enumerate() {
+        sheet.worksheet.write_with_format(0, j as u16, *title, &sheet.format.header)?;
+        sheet.worksheet.set_column_width(j as u16, *width)?;
+    }
+    Ok(())
+}
+
+// Joiners, movers and leavers, one row per identity
+pub struct IdentityChangesSheet<'a>(pub &'a [IdentityChangeData]);
+impl IdentityChangesSheet<'_> {
+    pub fn print(&mut self, sheet: &mut Sheet) -> Result<(), XlsxError>  {
+        print_sheet_header(sheet, &[("Identity", 35.0), ("Movement", 10.0), ("Changes", 80.0), ("Accounts", 10.0), ("Owned groups", 12.0)])?;
+        for (i, c) in (1..).zip(self.0.iter()) {
+            sheet.worksheet.write(i, 0, &c.identity.display_name)?;
+            sheet.worksheet.write(i, 1, &c.movement)?;
+            sheet.worksheet.write(i, 2, c.changes.join("; "))?;
+            let accounts: usize = c.identity.personal_accounts.values().chain(c.identity.owned_accounts.values()).map(Vec::len).sum();
+            sheet.worksheet.write(i, 3, accounts as u32)?;
+            sheet.worksheet.write(i, 4, c.identity.owned_groups.values().map(Vec::len).sum::<usize>() as u32)?;
+        }
+        Ok(())
+    }
+}
+
+// Access of movers and leavers in each target system, one row per account or owned group. Joiners are left out
+pub struct AccessStillHeldSheet<'a>(pub &'a [IdentityChangeData]);
+impl AccessStillHeldSheet<'_> {
+    pub fn print(&mut self, sheet: &mut Sheet) -> Result<(), XlsxError>  {
+        print_sheet_header(sheet, &[("Identity", 35.0), ("Movement", 10.0), ("Target system", 15.0), ("Relation", 15.0),
+            ("Account / group", 30.0), ("Enabled", 8.0), ("Entitlements", 80.0)])?;
+        let mut i = 1;
+        for c in self.0.iter().filter(|c| c.movement != "Joiner") {
+            for (relation, accounts) in [("Personal account", &c.identity.personal_accounts), ("Owned account", &c.identity.owned_accounts)] {
+                let mut ts_uids: Vec<&String> = accounts.keys().collect();
+                ts_uids.sort();
+                for ts_uid in ts_uids {
+                    for a in &accounts[ts_uid] {
+                        let entitlements: Vec<&str> = a.entitlements.iter().chain(&a.indirect_entitlements).map(|e| e.display_name.as_str()).collect();
+                        IdentityChangeRow { change: c, ts_uid, relation, name: &a.display_name }.print(i, sheet)?;
+                        sheet.worksheet.write(i, 5, a.enabled.trim())?;
+                        sheet.worksheet.write(i, 6, entitlements.join(", "))?;
+                        i += 1;
+                    }
+                }
+            }
+            let mut ts_uids: Vec<&String> = c.identity.owned_groups.keys().collect();
+            ts_uids.sort();
+            for ts_uid in ts_uids {
+                for g in &c.identity.owned_groups[ts_uid] {
+                    IdentityChangeRow { change: c, ts_uid, relation: "Owned group", name: &g.display_name }.print(i, sheet)?;
+                    i += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+struct IdentityChangeRow<'a> {
+    change: &'a IdentityChangeData,
+    ts_uid: &'a str,
+    relation: &'a str,
+    name: &'a str,
+}
+impl IdentityChangeRow<'_> {
+    fn print(&self, i: u32, sheet: &mut Sheet) -> Result<(), XlsxError> {
+        sheet.worksheet.write(i, 0, &self.change.identity.display_name)?;
+        sheet.worksheet.write(i, 1, &self.change.movement)?;
+        sheet.worksheet.write(i, 2, self.ts_uid)?;
+        sheet.worksheet.write(i, 3, self.relation)?;
+        sheet.worksheet.write(i, 4, self.name)?;
+        Ok(())
+    }
+}